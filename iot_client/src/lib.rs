@@ -1,37 +1,336 @@
 use iot_protocol::iot_client::IotClient;
 use iot_protocol::iot_error::{ConnectError, RequestError};
-use iot_protocol::iot_message::{CommandType, IotMessage};
+use iot_protocol::iot_message::{
+    CommandType, DeviceEvent, DeviceGroup, DeviceInfo, EnergyReport, FaultAction,
+    FaultInjectionRequest, GroupReport, GroupRequest, IotMessage, NewDevice, NewTimer, PowerReport,
+    Scene, SceneReport, ScheduleInfo, StatusReport, Subscription, TelemetryReport,
+    TemperatureReport, TripRecord,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use smart_socket::electrical::ElectricalLimits;
+use smart_socket::schedule::WeeklySchedule;
+use smart_socket::{SmartDeviceKind, SmartDevicePowerState, TemperatureThresholds};
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+/// Идентификатор розетки, которой адресуются команды по умолчанию
+pub const DEFAULT_DEVICE_ID: u8 = 47;
+
 /// Клиент чата.
 pub struct SmartClient {
     clnt: IotClient,
+    device_id: u8,
 }
 
 impl SmartClient {
     /// Подключаемся к серверу.
     pub fn new<Addr: ToSocketAddrs>(addr: Addr) -> Result<Self, ConnectError> {
         let clnt = IotClient::connect(addr)?;
-        Ok(Self { clnt })
+        Ok(Self {
+            clnt,
+            device_id: DEFAULT_DEVICE_ID,
+        })
     }
 
-    /// Включение розетки.
-    pub fn turn_on(&mut self) -> Result<String, RequestError> {
-        let request = IotMessage::new(47, CommandType::SetPowerOn, "Dummy".to_string());
-        let response = self.clnt.send_request(request)?;
-        Ok(response.get_message_data())
+    /// Выбор розетки, которой адресуются последующие команды.
+    pub fn select_device(&mut self, device_id: u8) {
+        self.device_id = device_id;
     }
 
-    /// Выключение розетки.
-    pub fn turn_off(&mut self) -> Result<String, RequestError> {
-        let request = IotMessage::new(47, CommandType::SetPowerOff, "Dummy".to_string());
-        let response = self.clnt.send_request(request)?;
-        Ok(response.get_message_data())
+    /// Идентификатор розетки, которой адресуются команды.
+    pub fn get_device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    /// Список устройств сервера.
+    pub fn list_devices(&mut self) -> Result<Vec<DeviceInfo>, RequestError> {
+        self.exchange(IotMessage::new(0, CommandType::ListDevices, String::new()))
+    }
+
+    /// Добавление на сервер устройства вида `kind` с идентификатором `device_id` и именем `name`.
+    pub fn add_device(
+        &mut self,
+        device_id: u8,
+        kind: SmartDeviceKind,
+        name: &str,
+    ) -> Result<(), RequestError> {
+        let new_device = NewDevice {
+            kind,
+            name: name.to_string(),
+        };
+        self.send(device_id, CommandType::AddDevice, &new_device)
+    }
+
+    /// Удаление с сервера устройства с идентификатором `device_id`.
+    pub fn remove_device(&mut self, device_id: u8) -> Result<(), RequestError> {
+        self.exchange(IotMessage::new(
+            device_id,
+            CommandType::RemoveDevice,
+            String::new(),
+        ))
+    }
+
+    /// Включение розетки. Возвращает состояние розетки после включения.
+    pub fn turn_on(&mut self) -> Result<StatusReport, RequestError> {
+        self.execute(CommandType::SetPowerOn)
+    }
+
+    /// Выключение розетки. Возвращает состояние розетки после выключения.
+    pub fn turn_off(&mut self) -> Result<StatusReport, RequestError> {
+        self.execute(CommandType::SetPowerOff)
     }
 
     /// Получение состояния розетки
-    pub fn get_state(&mut self) -> Result<String, RequestError> {
-        let request = IotMessage::new(47, CommandType::GetStatus, "Dummy".to_string());
+    pub fn get_state(&mut self) -> Result<StatusReport, RequestError> {
+        self.execute(CommandType::GetStatus)
+    }
+
+    /// Получение потребляемой розеткой мощности, Вт
+    pub fn get_power(&mut self) -> Result<f32, RequestError> {
+        let report: PowerReport = self.execute(CommandType::GetPower)?;
+        Ok(report.power_consumption_w)
+    }
+
+    /// Переименование розетки
+    pub fn rename(&mut self, name: &str) -> Result<(), RequestError> {
+        self.send(self.device_id, CommandType::Rename, &name)
+    }
+
+    /// Получение показаний счётчика энергии розетки: всего, с момента сброса, по суткам и месяцам.
+    pub fn get_energy(&mut self) -> Result<EnergyReport, RequestError> {
+        self.execute(CommandType::GetEnergy)
+    }
+
+    /// Сброс сбрасываемого счётчика энергии розетки. Возвращает показания после сброса.
+    pub fn reset_energy(&mut self) -> Result<EnergyReport, RequestError> {
+        self.execute(CommandType::ResetEnergy)
+    }
+
+    /// Получение напряжения сети, тока и коэффициента мощности розетки.
+    pub fn get_telemetry(&mut self) -> Result<TelemetryReport, RequestError> {
+        self.execute(CommandType::GetTelemetry)
+    }
+
+    /// Изменение пределов напряжения, В, и тока, А, розетки.
+    /// Возвращает показания розетки с новыми пределами.
+    pub fn set_limits(
+        &mut self,
+        max_voltage_v: f32,
+        max_current_a: f32,
+    ) -> Result<TelemetryReport, RequestError> {
+        let limits = ElectricalLimits {
+            max_voltage_v,
+            max_current_a,
+        };
+        self.send(self.device_id, CommandType::SetLimits, &limits)
+    }
+
+    /// Сброс неисправности розетки после защитного отключения.
+    /// Возвращает состояние розетки после сброса: розетка остаётся выключенной.
+    pub fn reset_fault(&mut self) -> Result<StatusReport, RequestError> {
+        self.execute(CommandType::ResetFault)
+    }
+
+    /// Получение истории защитных отключений розетки, от старых к новым.
+    pub fn get_trip_history(&mut self) -> Result<Vec<TripRecord>, RequestError> {
+        self.execute(CommandType::GetTripHistory)
+    }
+
+    /// Таймер: установка состояния питания `action` выбранному устройству через `delay`
+    /// (с точностью до секунды). Возвращает описание созданного таймера.
+    pub fn add_timer(
+        &mut self,
+        delay: Duration,
+        action: SmartDevicePowerState,
+    ) -> Result<ScheduleInfo, RequestError> {
+        let timer = NewTimer {
+            delay_s: delay.as_secs(),
+            action,
+        };
+        self.send(self.device_id, CommandType::AddTimer, &timer)
+    }
+
+    /// Недельное расписание выбранного устройства (время по UTC).
+    /// Возвращает описание созданного расписания.
+    pub fn add_schedule(
+        &mut self,
+        schedule: &WeeklySchedule,
+    ) -> Result<ScheduleInfo, RequestError> {
+        self.send(self.device_id, CommandType::AddSchedule, schedule)
+    }
+
+    /// Таймеры и расписания выбранного устройства.
+    pub fn list_schedules(&mut self) -> Result<Vec<ScheduleInfo>, RequestError> {
+        self.execute(CommandType::ListSchedules)
+    }
+
+    /// Удаление таймера или расписания `id` выбранного устройства.
+    pub fn delete_schedule(&mut self, id: u32) -> Result<(), RequestError> {
+        self.send(self.device_id, CommandType::DeleteSchedule, &id)
+    }
+
+    /// Сохранение на сервере сцены `name`: состояния питания `states` по идентификаторам устройств.
+    pub fn save_scene(
+        &mut self,
+        name: &str,
+        states: BTreeMap<u8, SmartDevicePowerState>,
+    ) -> Result<(), RequestError> {
+        let scene = Scene {
+            name: name.to_string(),
+            states,
+        };
+        self.send(self.device_id, CommandType::SaveScene, &scene)
+    }
+
+    /// Список сцен, сохранённых на сервере.
+    pub fn list_scenes(&mut self) -> Result<Vec<Scene>, RequestError> {
+        self.execute(CommandType::ListScenes)
+    }
+
+    /// Применение сцены `name`. Возвращает результат по каждому устройству сцены:
+    /// неисправное устройство не мешает переключению остальных.
+    pub fn apply_scene(&mut self, name: &str) -> Result<SceneReport, RequestError> {
+        self.send(self.device_id, CommandType::ApplyScene, &name)
+    }
+
+    /// Сохранение на сервере группы `name` из устройств `devices`; группа с тем же именем заменяется.
+    pub fn save_group(&mut self, name: &str, devices: BTreeSet<u8>) -> Result<(), RequestError> {
+        let group = DeviceGroup {
+            name: name.to_string(),
+            devices,
+        };
+        self.send(self.device_id, CommandType::SaveGroup, &group)
+    }
+
+    /// Список групп устройств, сохранённых на сервере.
+    pub fn list_groups(&mut self) -> Result<Vec<DeviceGroup>, RequestError> {
+        self.execute(CommandType::ListGroups)
+    }
+
+    /// Удаление группы `name`.
+    pub fn delete_group(&mut self, name: &str) -> Result<(), RequestError> {
+        self.send(self.device_id, CommandType::DeleteGroup, &name)
+    }
+
+    /// Отправка команды без данных всем устройствам группы `group`.
+    /// Возвращает код результата и данные ответа каждого устройства.
+    pub fn group_command(
+        &mut self,
+        group: &str,
+        command: CommandType,
+    ) -> Result<GroupReport, RequestError> {
+        let request = GroupRequest::new(group, command, String::new());
+        self.send(self.device_id, CommandType::GroupCommand, &request)
+    }
+
+    /// Подписка на события устройств; повторная подписка заменяет предыдущую.
+    /// События получаются через `next_event` или `events`.
+    pub fn subscribe(&mut self, subscription: &Subscription) -> Result<(), RequestError> {
+        self.send(self.device_id, CommandType::Subscribe, subscription)
+    }
+
+    /// Отмена подписки на события. События, полученные до отмены, остаются доступны.
+    pub fn unsubscribe(&mut self) -> Result<(), RequestError> {
+        self.execute(CommandType::Unsubscribe)
+    }
+
+    /// Ожидание очередного события устройства.
+    pub fn next_event(&mut self) -> Result<DeviceEvent, RequestError> {
+        let event = self.clnt.next_event()?;
+        Ok(event.get_payload()?)
+    }
+
+    /// События устройств по мере их получения.
+    /// Итератор завершается после ошибки соединения.
+    pub fn events(&mut self) -> Events<'_> {
+        Events {
+            client: self,
+            closed: false,
+        }
+    }
+
+    /// Внесение неисправностей в розетку для проверки автоматики, `token` - токен администратора.
+    /// Возвращает состояние розетки после выполнения действия.
+    pub fn inject_fault(
+        &mut self,
+        token: &str,
+        action: FaultAction,
+    ) -> Result<StatusReport, RequestError> {
+        let request = FaultInjectionRequest {
+            token: token.to_string(),
+            action,
+        };
+        self.send(self.device_id, CommandType::InjectFault, &request)
+    }
+
+    /// Получение температуры и порогов выбранного термометра.
+    pub fn get_temperature(&mut self) -> Result<TemperatureReport, RequestError> {
+        self.execute(CommandType::GetTemperature)
+    }
+
+    /// Изменение порогов температуры выбранного термометра, °C.
+    /// Возвращает показания термометра с новыми порогами.
+    pub fn set_thresholds(
+        &mut self,
+        low_c: f32,
+        high_c: f32,
+    ) -> Result<TemperatureReport, RequestError> {
+        let thresholds = TemperatureThresholds { low_c, high_c };
+        self.send(self.device_id, CommandType::SetThresholds, &thresholds)
+    }
+
+    /// Отправка команды без данных розетке.
+    fn execute<Response: DeserializeOwned>(
+        &mut self,
+        command: CommandType,
+    ) -> Result<Response, RequestError> {
+        self.exchange(IotMessage::new(self.device_id, command, String::new()))
+    }
+
+    /// Отправка запроса с данными `payload` и разбор данных ответа.
+    ///
+    /// Данные, не помещающиеся в посылку, возвращаются как `RequestError::BadPayload`
+    /// без отправки запроса.
+    fn send<Request: Serialize + ?Sized, Response: DeserializeOwned>(
+        &mut self,
+        device_id: u8,
+        command: CommandType,
+        payload: &Request,
+    ) -> Result<Response, RequestError> {
+        let request = IotMessage::try_with_payload(device_id, command, payload)?;
+        self.exchange(request)
+    }
+
+    /// Отправка запроса и разбор данных ответа.
+    ///
+    /// Отказ сервера или устройства возвращается как `RequestError::Device`.
+    fn exchange<Response: DeserializeOwned>(
+        &mut self,
+        request: IotMessage,
+    ) -> Result<Response, RequestError> {
         let response = self.clnt.send_request(request)?;
-        Ok(response.get_message_data())
+        response.get_status().into_result()?;
+        Ok(response.get_payload()?)
+    }
+}
+
+/// Итератор событий устройств, см. `SmartClient::events`
+pub struct Events<'a> {
+    client: &'a mut SmartClient,
+    closed: bool,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<DeviceEvent, RequestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.closed {
+            return None;
+        }
+        let event = self.client.next_event();
+        self.closed = matches!(event, Err(RequestError::Send(_) | RequestError::Recv(_)));
+        Some(event)
     }
 }
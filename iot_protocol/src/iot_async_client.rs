@@ -0,0 +1,88 @@
+use crate::iot_error::{ConnectError, ReceptionError, RequestError};
+use crate::iot_handshake::{self, Capabilities, HandshakeOffer, Negotiated};
+use crate::iot_message::{CommandType, IotMessage};
+use crate::iot_spec::FrameVersion;
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// Асинхронный клиент IoT.
+pub struct AsyncIotClient<Stream = TcpStream> {
+    stream: Stream,
+    negotiated: Negotiated,
+    last_seq: u16,
+}
+
+impl AsyncIotClient<TcpStream> {
+    /// Пытаемся подключится к серверу и проверяем, что он поддерживает IoT protocol.
+    ///
+    /// Подписка на события не предлагается: клиент не обрабатывает посылки `Event`.
+    pub async fn connect<Addrs>(addrs: Addrs) -> Result<Self, ConnectError>
+    where
+        Addrs: ToSocketAddrs,
+    {
+        Self::connect_with(addrs, &HandshakeOffer::async_default()).await
+    }
+
+    /// Подключение с заданными версиями протокола и возможностями.
+    pub async fn connect_with<Addrs>(
+        addrs: Addrs,
+        offer: &HandshakeOffer,
+    ) -> Result<Self, ConnectError>
+    where
+        Addrs: ToSocketAddrs,
+    {
+        let stream = TcpStream::connect(addrs).await?;
+        Self::handshake(stream, offer).await
+    }
+}
+
+impl<Stream> AsyncIotClient<Stream>
+where
+    Stream: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Проводим handshake с сервером по произвольному потоку (см. `iot_handshake`).
+    pub async fn handshake(
+        mut stream: Stream,
+        offer: &HandshakeOffer,
+    ) -> Result<Self, ConnectError> {
+        let negotiated = iot_handshake::client_handshake_async(&mut stream, offer).await?;
+        Ok(Self {
+            stream,
+            negotiated,
+            last_seq: 0,
+        })
+    }
+
+    /// Отправка запроса на сервер и получение ответа.
+    ///
+    /// Запросу присваивается очередной номер; ответ с чужим номером считается ошибкой.
+    pub async fn send_request(&mut self, req: IotMessage) -> Result<IotMessage, RequestError> {
+        self.last_seq = self.last_seq.checked_add(1).unwrap_or(1);
+        let seq = self.last_seq;
+        let version = self.negotiated.version;
+
+        crate::send_message_async(req.with_seq(seq), version, &mut self.stream).await?;
+        let Some(response) = crate::receive_message_or_eof_async(&mut self.stream, version).await?
+        else {
+            return Err(ReceptionError::Io(io::ErrorKind::UnexpectedEof.into()).into());
+        };
+        if response.get_command_type() == CommandType::ProtocolError {
+            return Err(RequestError::Rejected(response.get_message_data()));
+        }
+        if version >= FrameVersion::V2 && response.get_seq() != seq {
+            return Err(RequestError::UnexpectedResponse(response.get_seq()));
+        }
+        Ok(response)
+    }
+
+    /// Согласованная версия протокола.
+    pub fn version(&self) -> FrameVersion {
+        self.negotiated.version
+    }
+
+    /// Возможности, поддерживаемые и клиентом, и сервером.
+    pub fn capabilities(&self) -> Capabilities {
+        self.negotiated.capabilities
+    }
+}
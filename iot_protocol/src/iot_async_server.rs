@@ -0,0 +1,240 @@
+use crate::iot_error::{ConnectError, ReceptionError, RequestError};
+use crate::iot_handshake::{self, Capabilities, HandshakeOffer, Negotiated};
+use crate::iot_message::{CommandType, IotMessage, ResponseStatus};
+use crate::iot_spec::FrameVersion;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Асинхронный обработчик запросов клиента.
+///
+/// Реализован для замыканий вида `FnMut(IotMessage) -> impl Future<Output = IotMessage>`.
+pub trait AsyncRequestHandler {
+    /// Формирование ответа на запрос
+    fn handle(&mut self, request: IotMessage) -> impl Future<Output = IotMessage> + Send;
+}
+
+impl<F, Fut> AsyncRequestHandler for F
+where
+    F: FnMut(IotMessage) -> Fut,
+    Fut: Future<Output = IotMessage> + Send,
+{
+    fn handle(&mut self, request: IotMessage) -> impl Future<Output = IotMessage> + Send {
+        self(request)
+    }
+}
+
+/// Асинхронный IoT сервер.
+pub struct AsyncIotServer {
+    tcp: TcpListener,
+    offer: HandshakeOffer,
+}
+
+impl AsyncIotServer {
+    /// Закрепляем сервер на сокете.
+    ///
+    /// Подписка на события не предлагается: сервер не отправляет посылки `Event`.
+    pub async fn bind<Addrs>(addrs: Addrs) -> io::Result<Self>
+    where
+        Addrs: ToSocketAddrs,
+    {
+        let tcp = TcpListener::bind(addrs).await?;
+        Ok(Self {
+            tcp,
+            offer: HandshakeOffer::async_default(),
+        })
+    }
+
+    /// Задаём поддерживаемые сервером версии протокола и возможности.
+    pub fn with_offer(mut self, offer: HandshakeOffer) -> Self {
+        self.offer = offer;
+        self
+    }
+
+    /// Адрес, на котором сервер принимает соединения.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+
+    /// Принимаем входящее соединение и производим handshake.
+    ///
+    /// Handshake выполняется в вызывающей задаче; чтобы медленный клиент не задерживал
+    /// приём остальных, используйте `accept_stream` и проводите handshake в отдельной задаче.
+    pub async fn accept(&self) -> Result<AsyncIotConnection, ConnectError> {
+        let (stream, _) = self.tcp.accept().await?;
+        AsyncIotConnection::handshake(stream, &self.offer).await
+    }
+
+    /// Принимаем входящее соединение без handshake.
+    pub async fn accept_stream(&self) -> io::Result<(TcpStream, HandshakeOffer)> {
+        let (stream, _) = self.tcp.accept().await?;
+        Ok((stream, self.offer.clone()))
+    }
+}
+
+/// Асинхронное соединение с клиентом.
+/// Позволяет обрабатывать запросы.
+pub struct AsyncIotConnection<Stream = TcpStream> {
+    stream: Stream,
+    negotiated: Negotiated,
+}
+
+impl<Stream> AsyncIotConnection<Stream>
+where
+    Stream: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Проводим handshake с клиентом по произвольному потоку (см. `iot_handshake`).
+    pub async fn handshake(
+        mut stream: Stream,
+        offer: &HandshakeOffer,
+    ) -> Result<Self, ConnectError> {
+        let negotiated = iot_handshake::server_handshake_async(&mut stream, offer).await?;
+        Ok(Self { stream, negotiated })
+    }
+
+    /// Обрабатываем запрос и возвращаем ответ используя логику
+    /// предоставленную вызывающей стороной.
+    ///
+    /// Некорректные запросы отклоняются так же, как в `IotConnection::process_request`.
+    pub async fn process_request<H>(&mut self, handler: &mut H) -> Result<(), RequestError>
+    where
+        H: AsyncRequestHandler,
+    {
+        let Some(request) = self.next_request().await? else {
+            return Err(ReceptionError::Io(io::ErrorKind::UnexpectedEof.into()).into());
+        };
+        let seq = request.get_seq();
+        let response = handler.handle(request).await;
+        self.send_response(response.with_seq(seq)).await
+    }
+
+    /// Обрабатываем запросы, пока клиент не закроет соединение, см. `IotConnection::serve`.
+    pub async fn serve<H>(&mut self, mut handler: H) -> Result<(), RequestError>
+    where
+        H: AsyncRequestHandler,
+    {
+        loop {
+            match self.next_request().await {
+                Ok(Some(request)) => {
+                    let seq = request.get_seq();
+                    let response = handler.handle(request).await;
+                    self.send_response(response.with_seq(seq)).await?;
+                }
+                Ok(None) => return Ok(()),
+                Err(RequestError::Recv(ReceptionError::BadCRC | ReceptionError::Decode(_))) => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Получение очередного запроса.
+    ///
+    /// Возвращает `None`, если клиент закрыл соединение между запросами.
+    /// Некорректный запрос отклоняется ответом `CommandType::ProtocolError`.
+    pub async fn next_request(&mut self) -> Result<Option<IotMessage>, RequestError> {
+        match crate::receive_message_or_eof_async(&mut self.stream, self.negotiated.version).await {
+            Ok(request) => Ok(request),
+            Err(e @ (ReceptionError::BadCRC | ReceptionError::Decode(_))) => {
+                self.reject_request(&e.to_string()).await?;
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Отправка ответа на запрос.
+    pub async fn send_response(&mut self, response: IotMessage) -> Result<(), RequestError> {
+        crate::send_message_async(response, self.negotiated.version, &mut self.stream).await?;
+        Ok(())
+    }
+
+    /// Отправка клиенту ответа об ошибке протокола
+    pub async fn reject_request(&mut self, reason: &str) -> Result<(), RequestError> {
+        let response = IotMessage::new(0, CommandType::ProtocolError, reason.to_string())
+            .with_status(ResponseStatus::BadRequest);
+        self.send_response(response).await
+    }
+
+    /// Согласованная версия протокола.
+    pub fn version(&self) -> FrameVersion {
+        self.negotiated.version
+    }
+
+    /// Возможности, поддерживаемые и клиентом, и сервером.
+    pub fn capabilities(&self) -> Capabilities {
+        self.negotiated.capabilities
+    }
+}
+
+impl AsyncIotConnection<TcpStream> {
+    /// Address of connected client
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_async_client::AsyncIotClient;
+
+    #[tokio::test]
+    async fn test_duplex_serve_until_disconnect() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+
+        let server = tokio::spawn(async move {
+            let mut connection =
+                AsyncIotConnection::handshake(server_stream, &HandshakeOffer::default())
+                    .await
+                    .unwrap();
+            connection
+                .serve(|request: IotMessage| async move {
+                    IotMessage::new(
+                        request.get_id(),
+                        request.get_command_type(),
+                        format!("echo {}", request.get_message_data()),
+                    )
+                })
+                .await
+        });
+
+        let mut client = AsyncIotClient::handshake(client_stream, &HandshakeOffer::async_default())
+            .await
+            .unwrap();
+        assert_eq!(client.capabilities(), Capabilities::CRC_VALIDATION);
+        for data in ["one", "two"] {
+            let request = IotMessage::new(47, CommandType::GetStatus, data.to_string());
+            let response = client.send_request(request).await.unwrap();
+            assert_eq!(response.get_message_data(), format!("echo {data}"));
+        }
+        drop(client);
+
+        assert!(server.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_server_and_client() {
+        let server = AsyncIotServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let mut connection = server.accept().await.unwrap();
+            let mut handler = |request: IotMessage| async move {
+                IotMessage::new(request.get_id(), request.get_command_type(), String::new())
+            };
+            connection.process_request(&mut handler).await
+        });
+
+        let mut client = AsyncIotClient::connect(addr).await.unwrap();
+        let request = IotMessage::new(47, CommandType::SetPowerOn, String::new());
+        let response = client.send_request(request.clone()).await.unwrap();
+
+        assert_eq!(response.get_id(), request.get_id());
+        assert_eq!(response.get_command_type(), request.get_command_type());
+        assert!(handle.await.unwrap().is_ok());
+    }
+}
@@ -1,40 +1,293 @@
 use crate::iot_error::{ConnectError, RequestError};
-use crate::iot_message::IotMessage;
-use std::io::{Read, Write};
+use crate::iot_handshake::{self, Capabilities, HandshakeOffer, Negotiated};
+use crate::iot_message::{CommandType, IotMessage};
+use crate::iot_spec::FrameVersion;
+use std::collections::{HashMap, VecDeque};
 use std::net::{TcpStream, ToSocketAddrs};
 
 /// Клиент IoT.
+///
+/// Поддерживает конвейерную отправку запросов: `submit` отправляет запрос, не дожидаясь ответа,
+/// и возвращает его номер, а `wait_response` возвращает ответ на запрос с этим номером.
+/// Ответы на другие запросы, пришедшие раньше, сохраняются до их востребования.
+/// События, отправленные сервером после подписки, сохраняются до вызова `next_event`.
 pub struct IotClient {
     stream: TcpStream,
+    negotiated: Negotiated,
+    last_seq: u16,
+    /// Номера запросов, ожидающих ответа, в порядке отправки
+    outstanding: VecDeque<u16>,
+    /// Полученные, но ещё не востребованные ответы
+    completed: HashMap<u16, IotMessage>,
+    /// Полученные, но ещё не востребованные события
+    events: VecDeque<IotMessage>,
 }
 
 impl IotClient {
     /// Пытаемся подключится к серверу и проверяем, что он поддерживает STP.
     pub fn connect<Addrs>(addrs: Addrs) -> Result<Self, ConnectError>
+    where
+        Addrs: ToSocketAddrs,
+    {
+        Self::connect_with(addrs, &HandshakeOffer::default())
+    }
+
+    /// Подключение с заданными версиями протокола и возможностями.
+    pub fn connect_with<Addrs>(addrs: Addrs, offer: &HandshakeOffer) -> Result<Self, ConnectError>
     where
         Addrs: ToSocketAddrs,
     {
         let stream = TcpStream::connect(addrs)?;
-        Self::try_handshake(stream)
-    }
-
-    /// Проводим handshake, чтобы убедиться, что сервер поддерживает IoT protocol:
-    /// 1) отправляем байты "iot_clnt",
-    /// 1) ожидаем байты "iot_serv" в ответ.
-    fn try_handshake(mut stream: TcpStream) -> Result<Self, ConnectError> {
-        stream.write_all(b"iot_clnt")?;
-        let mut buf = [0; 8];
-        stream.read_exact(&mut buf)?;
-        if &buf != b"iot_serv" {
-            return Err(ConnectError::BadHandshake);
-        }
-        Ok(Self { stream })
+        Self::try_handshake(stream, offer)
+    }
+
+    /// Проводим handshake, чтобы убедиться, что сервер поддерживает IoT protocol,
+    /// и согласовать версию протокола и возможности (см. `iot_handshake`).
+    fn try_handshake(mut stream: TcpStream, offer: &HandshakeOffer) -> Result<Self, ConnectError> {
+        let negotiated = iot_handshake::client_handshake(&mut stream, offer)?;
+        Ok(Self {
+            stream,
+            negotiated,
+            last_seq: 0,
+            outstanding: VecDeque::new(),
+            completed: HashMap::new(),
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Согласованная версия протокола.
+    pub fn version(&self) -> FrameVersion {
+        self.negotiated.version
+    }
+
+    /// Возможности, поддерживаемые и клиентом, и сервером.
+    pub fn capabilities(&self) -> Capabilities {
+        self.negotiated.capabilities
     }
 
     /// Отправка запроса на сервер и получение ответа.
     pub fn send_request(&mut self, req: IotMessage) -> Result<IotMessage, RequestError> {
-        crate::send_message(req, &mut self.stream)?;
-        let response = crate::receive_message(&mut self.stream)?;
-        Ok(response)
+        let seq = self.submit(req)?;
+        self.wait_response(seq)
+    }
+
+    /// Конвейерная отправка нескольких запросов и получение ответов на них
+    /// в порядке следования запросов.
+    pub fn send_requests<Requests>(
+        &mut self,
+        requests: Requests,
+    ) -> Result<Vec<Result<IotMessage, RequestError>>, RequestError>
+    where
+        Requests: IntoIterator<Item = IotMessage>,
+    {
+        let seqs = requests
+            .into_iter()
+            .map(|req| self.submit(req))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut responses = Vec::with_capacity(seqs.len());
+        for seq in seqs {
+            match self.wait_response(seq) {
+                Err(e @ (RequestError::Send(_) | RequestError::Recv(_))) => return Err(e),
+                result => responses.push(result),
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Отправка запроса без ожидания ответа. Возвращает номер, присвоенный запросу.
+    pub fn submit(&mut self, req: IotMessage) -> Result<u16, RequestError> {
+        let seq = self.next_seq();
+        crate::send_message(req.with_seq(seq), self.negotiated.version, &mut self.stream)?;
+        self.outstanding.push_back(seq);
+        Ok(seq)
+    }
+
+    /// Ожидание ответа на запрос с номером `seq`.
+    ///
+    /// Ответы на другие запросы, полученные во время ожидания, сохраняются.
+    /// Ответ без номера запроса (версия протокола 1 или отказ в приёме искажённой посылки)
+    /// относится к самому раннему из запросов, ожидающих ответа.
+    pub fn wait_response(&mut self, seq: u16) -> Result<IotMessage, RequestError> {
+        loop {
+            if let Some(response) = self.completed.remove(&seq) {
+                if response.get_command_type() == CommandType::ProtocolError {
+                    return Err(RequestError::Rejected(response.get_message_data()));
+                }
+                return Ok(response);
+            }
+
+            if !self.outstanding.contains(&seq) {
+                return Err(RequestError::UnexpectedResponse(seq));
+            }
+            self.receive()?;
+        }
+    }
+
+    /// Ожидание очередного события от сервера (посылки `CommandType::Event`).
+    ///
+    /// Ответы на запросы, полученные во время ожидания, сохраняются.
+    pub fn next_event(&mut self) -> Result<IotMessage, RequestError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            self.receive()?;
+        }
+    }
+
+    /// Приём очередной посылки: события откладываются до `next_event`,
+    /// ответы - до `wait_response`
+    fn receive(&mut self) -> Result<(), RequestError> {
+        let response = crate::receive_message(&mut self.stream, self.negotiated.version)?;
+        if response.get_command_type() == CommandType::Event {
+            self.events.push_back(response);
+            return Ok(());
+        }
+
+        let response_seq = match response.get_seq() {
+            0 => self.outstanding.front().copied().unwrap_or(0),
+            response_seq => response_seq,
+        };
+        let Some(position) = self.outstanding.iter().position(|s| *s == response_seq) else {
+            return Err(RequestError::UnexpectedResponse(response_seq));
+        };
+        self.outstanding.remove(position);
+        self.completed.insert(response_seq, response);
+        Ok(())
+    }
+
+    /// Очередной свободный номер запроса, `0` не используется
+    fn next_seq(&mut self) -> u16 {
+        loop {
+            self.last_seq = self.last_seq.wrapping_add(1);
+            if self.last_seq != 0
+                && !self.outstanding.contains(&self.last_seq)
+                && !self.completed.contains_key(&self.last_seq)
+            {
+                return self.last_seq;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_message::{DeviceEvent, DeviceEventKind};
+    use crate::iot_server::IotServer;
+    use smart_socket::SmartDevicePowerState;
+    use std::thread;
+
+    /// Сервер отвечает на пачку запросов в обратном порядке,
+    /// клиент сопоставляет ответы с запросами по номеру
+    #[test]
+    fn test_out_of_order_responses() {
+        let server = IotServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut connection = server.accept().unwrap();
+            let mut requests = Vec::new();
+            for _ in 0..3 {
+                requests.push(connection.next_request().unwrap().unwrap());
+            }
+            for request in requests.into_iter().rev() {
+                let response = IotMessage::new(
+                    request.get_id(),
+                    request.get_command_type(),
+                    request.get_message_data(),
+                )
+                .with_seq(request.get_seq());
+                connection.send_response(response).unwrap();
+            }
+        });
+
+        let mut client = IotClient::connect(addr).unwrap();
+        assert_eq!(client.version(), FrameVersion::LATEST);
+
+        let requests: Vec<_> = (1..=3)
+            .map(|id| IotMessage::new(id, CommandType::GetStatus, format!("request {id}")))
+            .collect();
+        let responses = client.send_requests(requests).unwrap();
+
+        for (id, response) in (1..=3).zip(responses) {
+            let response = response.unwrap();
+            assert_eq!(response.get_id(), id);
+            assert_eq!(response.get_message_data(), format!("request {id}"));
+        }
+        handle.join().unwrap();
+    }
+
+    /// События, пришедшие раньше ответа, не мешают получить ответ и сохраняются
+    #[test]
+    fn test_events_between_responses() {
+        let server = IotServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut connection = server.accept().unwrap();
+            let events = connection.event_sender().unwrap();
+            connection
+                .serve(|request| {
+                    for at in 1..=2 {
+                        let event = DeviceEvent {
+                            device_id: request.get_id(),
+                            at,
+                            kind: DeviceEventKind::PowerState(SmartDevicePowerState::Enabled),
+                        };
+                        events.send(&event).unwrap();
+                    }
+                    request
+                })
+                .unwrap();
+        });
+
+        let mut client = IotClient::connect(addr).unwrap();
+        assert!(client.capabilities().contains(Capabilities::SUBSCRIPTIONS));
+        let request = IotMessage::new(47, CommandType::Subscribe, String::new());
+        let response = client.send_request(request).unwrap();
+        assert_eq!(response.get_command_type(), CommandType::Subscribe);
+
+        for at in 1..=2 {
+            let event = client.next_event().unwrap();
+            assert_eq!(event.get_command_type(), CommandType::Event);
+            assert_eq!(event.get_payload::<DeviceEvent>().unwrap().at, at);
+        }
+
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    /// По протоколу версии 1 ответы сопоставляются с запросами по порядку
+    #[test]
+    fn test_pipelining_over_v1() {
+        let server = IotServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut connection = server.accept().unwrap();
+            connection.serve(|request| request).unwrap();
+        });
+
+        let offer = HandshakeOffer {
+            versions: 1..=1,
+            ..HandshakeOffer::default()
+        };
+        let mut client = IotClient::connect_with(addr, &offer).unwrap();
+        assert_eq!(client.version(), FrameVersion::V1);
+
+        let first = client
+            .submit(IotMessage::new(1, CommandType::SetPowerOn, String::new()))
+            .unwrap();
+        let second = client
+            .submit(IotMessage::new(2, CommandType::SetPowerOff, String::new()))
+            .unwrap();
+
+        assert_eq!(client.wait_response(second).unwrap().get_id(), 2);
+        assert_eq!(client.wait_response(first).unwrap().get_id(), 1);
+
+        drop(client);
+        handle.join().unwrap();
     }
 }
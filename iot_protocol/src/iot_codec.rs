@@ -0,0 +1,127 @@
+use crate::iot_error::{ReceptionError, TransmissionError};
+use crate::iot_message::IotMessage;
+use crate::iot_spec::FrameVersion;
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Кодек посылок IoT protocol для `tokio_util::codec::Framed`.
+///
+/// Разбивает поток на посылки по длине из заголовка (см. `iot_spec`) и проверяет их
+/// так же, как `receive_message`: некорректная посылка целиком удаляется из буфера
+/// и возвращается как ошибка, поэтому следующая посылка разбирается с правильной позиции.
+#[derive(Debug, Clone, Copy)]
+pub struct IotCodec {
+    version: FrameVersion,
+}
+
+impl IotCodec {
+    /// Кодек для посылок в формате `version`
+    pub fn new(version: FrameVersion) -> Self {
+        Self { version }
+    }
+
+    /// Версия формата посылок
+    pub fn version(&self) -> FrameVersion {
+        self.version
+    }
+}
+
+/// Кодек для последней версии формата
+impl Default for IotCodec {
+    fn default() -> Self {
+        Self::new(FrameVersion::LATEST)
+    }
+}
+
+impl Decoder for IotCodec {
+    type Item = IotMessage;
+    type Error = ReceptionError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let header_length = self.version.header_length();
+        let Some(header) = src.get(..header_length) else {
+            src.reserve(header_length - src.len());
+            return Ok(None);
+        };
+
+        let frame_length = self.version.frame_length(header);
+        if src.len() < frame_length {
+            src.reserve(frame_length - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(frame_length);
+        Ok(Some(IotMessage::decode(&frame, self.version)?))
+    }
+}
+
+impl Encoder<IotMessage> for IotCodec {
+    type Error = TransmissionError;
+
+    fn encode(&mut self, item: IotMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.put_slice(&item.encode(self.version));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_message::CommandType;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+
+    #[test]
+    fn test_decode_partial_frames() {
+        let message = IotMessage::new(47, CommandType::SetPowerOn, "test".to_string());
+        let mut codec = IotCodec::default();
+        let raw_bytes = message.encode(codec.version());
+        let mut buffer = BytesMut::new();
+
+        for byte in &raw_bytes[..raw_bytes.len() - 1] {
+            buffer.put_u8(*byte);
+            assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        }
+        buffer.put_u8(raw_bytes[raw_bytes.len() - 1]);
+
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(message));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_bad_frame_is_skipped() {
+        let message = IotMessage::new(47, CommandType::GetStatus, String::new());
+        let mut codec = IotCodec::new(FrameVersion::V1);
+        let mut buffer = BytesMut::new();
+
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        buffer[FrameVersion::V1.header_length()] ^= 0xFF;
+        codec.encode(message.clone(), &mut buffer).unwrap();
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(ReceptionError::BadCRC)
+        ));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(message));
+    }
+
+    #[tokio::test]
+    async fn test_framed_duplex() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = Framed::new(client, IotCodec::default());
+        let mut server = Framed::new(server, IotCodec::default());
+
+        let messages = [
+            IotMessage::new(1, CommandType::SetPowerOn, String::new()).with_seq(1),
+            IotMessage::new(2, CommandType::SetPowerOff, "off".to_string()).with_seq(2),
+            IotMessage::new(3, CommandType::GetStatus, "статус".to_string()).with_seq(3),
+        ];
+
+        for message in messages.iter().cloned() {
+            client.send(message).await.unwrap();
+        }
+        for message in messages {
+            assert_eq!(server.next().await.unwrap().unwrap(), message);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::iot_error::DeviceError;
+use crate::iot_message::{CommandType, IotMessage};
+use crate::iot_spec::MAX_DATA_LENGTH;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Обработчик команды: по общему состоянию сервера и запросу формирует данные ответа
+type CommandHandler<State> =
+    Box<dyn Fn(&State, &IotMessage) -> Result<String, DeviceError> + Send + Sync>;
+
+/// Реестр команд, поддерживаемых сервером.
+///
+/// Сопоставляет коду команды обработчик. На команды без обработчика, в том числе
+/// на коды, не известные этой реализации, отвечает кодом результата
+/// `ResponseStatus::UnknownCommand`; если данные ответа не помещаются в посылку -
+/// кодом `ResponseStatus::BadRequest`.
+pub struct CommandRegistry<State> {
+    handlers: HashMap<CommandType, CommandHandler<State>>,
+}
+
+impl<State> CommandRegistry<State> {
+    /// Пустой реестр
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Регистрация обработчика команды `command`.
+    ///
+    /// Данные ответа, возвращённые обработчиком, передаются в виде JSON;
+    /// ошибка передаётся кодом результата и текстовым описанием.
+    /// Повторная регистрация заменяет прежний обработчик.
+    pub fn register<Payload, F>(&mut self, command: CommandType, handler: F) -> &mut Self
+    where
+        Payload: Serialize,
+        F: Fn(&State, &IotMessage) -> Result<Payload, DeviceError> + Send + Sync + 'static,
+    {
+        let handler = move |state: &State, request: &IotMessage| {
+            let payload = handler(state, request)?;
+            Ok(serde_json::to_string(&payload).expect("payload is not serializable to JSON"))
+        };
+        self.handlers.insert(command, Box::new(handler));
+        self
+    }
+
+    /// Есть ли обработчик команды `command`
+    pub fn supports(&self, command: CommandType) -> bool {
+        self.handlers.contains_key(&command)
+    }
+
+    /// Команды, для которых зарегистрированы обработчики
+    pub fn commands(&self) -> impl Iterator<Item = CommandType> + '_ {
+        self.handlers.keys().copied()
+    }
+
+    /// Обработка запроса: вызов обработчика команды и формирование ответа
+    pub fn dispatch(&self, state: &State, request: IotMessage) -> IotMessage {
+        let device_id = request.get_id();
+        let command = request.get_command_type();
+
+        let result = match self.handlers.get(&command) {
+            Some(handler) => handler(state, &request),
+            None => Err(DeviceError::UnknownCommand),
+        }
+        .and_then(|data| match data.len() {
+            0..=MAX_DATA_LENGTH => Ok(data),
+            _ => Err(DeviceError::BadRequest),
+        });
+
+        match result {
+            Ok(data) => IotMessage::new(device_id, command, data),
+            Err(e) => IotMessage::new(device_id, command, e.to_string()).with_status(e.into()),
+        }
+    }
+}
+
+impl<State> Default for CommandRegistry<State> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_message::ResponseStatus;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn counter_registry() -> CommandRegistry<AtomicU32> {
+        let mut registry = CommandRegistry::new();
+        registry
+            .register(CommandType::SetPowerOn, |counter: &AtomicU32, _: &IotMessage| {
+                Ok(counter.fetch_add(1, Ordering::SeqCst) + 1)
+            })
+            .register(CommandType::GetStatus, |_: &AtomicU32, request: &IotMessage| {
+                match request.get_id() {
+                    47 => Ok(()),
+                    _ => Err(DeviceError::UnknownDevice),
+                }
+            })
+            .register(CommandType::ListDevices, |_: &AtomicU32, _: &IotMessage| {
+                Ok("x".repeat(MAX_DATA_LENGTH))
+            });
+        registry
+    }
+
+    #[test]
+    fn test_dispatch_registered_command() {
+        let registry = counter_registry();
+        let counter = AtomicU32::new(0);
+
+        let request = IotMessage::new(47, CommandType::SetPowerOn, String::new());
+        let response = registry.dispatch(&counter, request.clone());
+        assert_eq!(response.get_status(), ResponseStatus::Ok);
+        assert_eq!(response.get_payload::<u32>().unwrap(), 1);
+        assert_eq!(registry.dispatch(&counter, request).get_message_data(), "2");
+
+        let request = IotMessage::new(9, CommandType::GetStatus, String::new());
+        let response = registry.dispatch(&counter, request);
+        assert_eq!(response.get_status(), ResponseStatus::UnknownDevice);
+        assert_eq!(response.get_id(), 9);
+    }
+
+    /// Данные ответа, не помещающиеся в посылку, заменяются ошибкой
+    #[test]
+    fn test_dispatch_oversized_response() {
+        let registry = counter_registry();
+        let counter = AtomicU32::new(0);
+
+        let request = IotMessage::new(47, CommandType::ListDevices, String::new());
+        let response = registry.dispatch(&counter, request);
+        assert_eq!(response.get_status(), ResponseStatus::BadRequest);
+        assert_eq!(
+            response.get_message_data(),
+            DeviceError::BadRequest.to_string()
+        );
+    }
+
+    /// Команды без обработчика, в том числе неизвестные, не ломают обработку
+    #[test]
+    fn test_dispatch_unsupported_command() {
+        let registry = counter_registry();
+        let counter = AtomicU32::new(0);
+
+        for command in [CommandType::SetPowerOff, CommandType::Unknown(0x7E)] {
+            assert!(!registry.supports(command));
+            let response = registry.dispatch(&counter, IotMessage::new(47, command, String::new()));
+            assert_eq!(response.get_status(), ResponseStatus::UnknownCommand);
+            assert_eq!(response.get_command_type(), command);
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
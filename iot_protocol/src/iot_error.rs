@@ -1,4 +1,7 @@
+use crate::iot_spec::MAX_DATA_LENGTH;
+use smart_socket::{SmartDeviceCommandError, SmartDeviceErrorCode};
 use std::error::Error;
+use std::ops::RangeInclusive;
 use std::{fmt, io};
 
 /// Ошибка при обмене данными с сервером.
@@ -9,6 +12,18 @@ pub enum RequestError {
 
     /// Ошибка приема.
     Recv(ReceptionError),
+
+    /// Сервер отклонил запрос как некорректный.
+    Rejected(String),
+
+    /// Получен ответ с номером запроса, который не ожидает ответа.
+    UnexpectedResponse(u16),
+
+    /// Сервер или устройство отказались выполнить запрос.
+    Device(DeviceError),
+
+    /// Данные запроса не помещаются в посылку или данные ответа не соответствуют ожидаемому типу.
+    BadPayload(PayloadError),
 }
 
 impl fmt::Display for RequestError {
@@ -16,6 +31,12 @@ impl fmt::Display for RequestError {
         match self {
             RequestError::Send(e) => write!(f, "send error: {e}"),
             RequestError::Recv(e) => write!(f, "recv error: {e}"),
+            RequestError::Rejected(reason) => write!(f, "request rejected: {reason}"),
+            RequestError::UnexpectedResponse(seq) => {
+                write!(f, "unexpected response to request #{seq}")
+            }
+            RequestError::Device(e) => write!(f, "request failed: {e}"),
+            RequestError::BadPayload(e) => write!(f, "bad payload: {e}"),
         }
     }
 }
@@ -32,11 +53,26 @@ impl From<ReceptionError> for RequestError {
     }
 }
 
+impl From<DeviceError> for RequestError {
+    fn from(value: DeviceError) -> Self {
+        Self::Device(value)
+    }
+}
+
+impl From<PayloadError> for RequestError {
+    fn from(value: PayloadError) -> Self {
+        Self::BadPayload(value)
+    }
+}
+
 impl Error for RequestError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             RequestError::Send(e) => Some(e),
             RequestError::Recv(e) => Some(e),
+            RequestError::Device(e) => Some(e),
+            RequestError::BadPayload(e) => Some(e),
+            RequestError::Rejected(_) | RequestError::UnexpectedResponse(_) => None,
         }
     }
 }
@@ -47,6 +83,12 @@ pub enum ConnectError {
     /// Неудачный handshake.
     BadHandshake,
 
+    /// У сторон нет общей версии протокола.
+    UnsupportedVersion {
+        local: RangeInclusive<u8>,
+        remote: RangeInclusive<u8>,
+    },
+
     /// Внутренняя ошибка IO.
     Io(io::Error),
 }
@@ -55,6 +97,14 @@ impl fmt::Display for ConnectError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::BadHandshake => write!(f, "bad handshake"),
+            Self::UnsupportedVersion { local, remote } => write!(
+                f,
+                "no common protocol version: local supports {}..={}, remote supports {}..={}",
+                local.start(),
+                local.end(),
+                remote.start(),
+                remote.end()
+            ),
             Self::Io(e) => write!(f, "IO error: {}", e),
         }
     }
@@ -70,7 +120,7 @@ impl Error for ConnectError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             Self::Io(e) => Some(e),
-            Self::BadHandshake => None,
+            Self::BadHandshake | Self::UnsupportedVersion { .. } => None,
         }
     }
 }
@@ -106,7 +156,10 @@ impl Error for TransmissionError {
 #[derive(Debug)]
 pub enum ReceptionError {
     Io(io::Error),
-    BadFormat,
+    /// Время ожидания истекло посреди посылки: её начало уже прочитано,
+    /// поэтому следующие посылки из этого потока прочитать нельзя
+    PartialFrame(io::Error),
+    Decode(DecodeError),
     BadCRC,
 }
 
@@ -114,7 +167,8 @@ impl fmt::Display for ReceptionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ReceptionError::Io(e) => write!(f, "Internal IO error occured: {}", e),
-            ReceptionError::BadFormat => write!(f, "Incorrect message format!"),
+            ReceptionError::PartialFrame(e) => write!(f, "Message is incomplete: {}", e),
+            ReceptionError::Decode(e) => write!(f, "Incorrect message format: {}", e),
             ReceptionError::BadCRC => write!(f, "Bad CRC!"),
         }
     }
@@ -126,11 +180,151 @@ impl From<io::Error> for ReceptionError {
     }
 }
 
+impl From<DecodeError> for ReceptionError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::BadCrc { .. } => ReceptionError::BadCRC,
+            e => ReceptionError::Decode(e),
+        }
+    }
+}
+
 impl Error for ReceptionError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            ReceptionError::Io(e) => Some(e),
+            ReceptionError::Io(e) | ReceptionError::PartialFrame(e) => Some(e),
+            ReceptionError::Decode(e) => Some(e),
             _ => None,
         }
     }
 }
+
+/// Ошибка разбора посылки из "сырых" байт
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Посылка короче заголовка
+    TruncatedHeader { length: usize },
+
+    /// Заявленная в заголовке длина данных не помещается в посылку
+    LengthMismatch { declared: usize, available: usize },
+
+    /// Неизвестный код результата
+    UnknownStatus(u8),
+
+    /// Данные посылки не являются корректной строкой UTF-8
+    InvalidUtf8,
+
+    /// После CRC остались лишние байты
+    TrailingBytes(usize),
+
+    /// Переданная CRC не совпадает с рассчитанной
+    BadCrc { expected: u16, received: u16 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::TruncatedHeader { length } => {
+                write!(f, "truncated header ({length} bytes)")
+            }
+            DecodeError::LengthMismatch {
+                declared,
+                available,
+            } => write!(
+                f,
+                "declared data length {declared} does not fit into {available} available bytes"
+            ),
+            DecodeError::UnknownStatus(code) => write!(f, "unknown response status {code:#04X}"),
+            DecodeError::InvalidUtf8 => write!(f, "message data is not valid UTF-8"),
+            DecodeError::TrailingBytes(count) => write!(f, "{count} trailing bytes after CRC"),
+            DecodeError::BadCrc { expected, received } => {
+                write!(
+                    f,
+                    "bad CRC: expected {expected:#06X}, received {received:#06X}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Отказ выполнить запрос, переданный в коде результата ответа (см. `ResponseStatus`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceError {
+    /// Некорректный запрос
+    BadRequest,
+
+    /// Обращение к несуществующему устройству
+    UnknownDevice,
+
+    /// Команда не поддерживается
+    UnknownCommand,
+
+    /// Недостаточно прав
+    Unauthorized,
+
+    /// Неисправность устройства
+    Fault(SmartDeviceErrorCode),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::BadRequest => write!(f, "bad request"),
+            DeviceError::UnknownDevice => write!(f, "unknown device"),
+            DeviceError::UnknownCommand => write!(f, "unsupported command"),
+            DeviceError::Unauthorized => write!(f, "unauthorized"),
+            DeviceError::Fault(code) => write!(f, "device fault: {code}"),
+        }
+    }
+}
+
+impl From<SmartDeviceCommandError> for DeviceError {
+    fn from(value: SmartDeviceCommandError) -> Self {
+        match value {
+            SmartDeviceCommandError::Unsupported => Self::UnknownCommand,
+            SmartDeviceCommandError::InvalidArgument => Self::BadRequest,
+            SmartDeviceCommandError::Malfunction(code) => Self::Fault(code),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+/// Ошибка формирования или разбора данных посылки
+#[derive(Debug)]
+pub enum PayloadError {
+    /// Данные не соответствуют ожидаемому типу или не сериализуются в JSON
+    Json(serde_json::Error),
+
+    /// Данные длиннее `MAX_DATA_LENGTH` байт (указана их длина)
+    TooLong(usize),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadError::Json(e) => write!(f, "invalid payload: {}", e),
+            PayloadError::TooLong(length) => write!(
+                f,
+                "payload of {length} bytes is longer than {MAX_DATA_LENGTH} bytes"
+            ),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PayloadError {
+    fn from(e: serde_json::Error) -> Self {
+        PayloadError::Json(e)
+    }
+}
+
+impl Error for PayloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PayloadError::Json(e) => Some(e),
+            PayloadError::TooLong(_) => None,
+        }
+    }
+}
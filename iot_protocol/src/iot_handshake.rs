@@ -0,0 +1,487 @@
+//! Handshake IoT protocol: согласование версии протокола и возможностей сторон.
+//!
+//! # Расширенный handshake
+//! 1) клиент отправляет `"iot_clnx"` + минимальная версия (1 байт) + максимальная версия (1 байт)
+//!    + битовая маска возможностей (4 байта, big-endian);
+//! 2) сервер отвечает `"iot_serx"` + минимальная версия + максимальная версия
+//!    + выбранная версия (1 байт, `0` - общей версии нет) + согласованные возможности (4 байта).
+//!
+//! Выбирается наибольшая версия, поддерживаемая обеими сторонами,
+//! возможности - пересечение масок сторон.
+//!
+//! # Устаревший handshake
+//! Клиент отправляет `"iot_clnt"`, сервер отвечает `"iot_serv"`.
+//! Такое соединение работает по версии 1 без дополнительных возможностей.
+
+use crate::iot_error::ConnectError;
+use crate::iot_spec::FrameVersion;
+use std::io::{Read, Write};
+use std::ops::{BitAnd, BitOr, RangeInclusive};
+
+/// Приветствие клиента в устаревшем handshake
+pub const CLIENT_MAGIC_LEGACY: &[u8; 8] = b"iot_clnt";
+
+/// Ответ сервера в устаревшем handshake
+pub const SERVER_MAGIC_LEGACY: &[u8; 8] = b"iot_serv";
+
+/// Приветствие клиента в расширенном handshake
+pub const CLIENT_MAGIC: &[u8; 8] = b"iot_clnx";
+
+/// Ответ сервера в расширенном handshake
+pub const SERVER_MAGIC: &[u8; 8] = b"iot_serx";
+
+/// Версия протокола, используемая при устаревшем handshake
+pub const LEGACY_VERSION: u8 = 1;
+
+/// Минимальная версия протокола, поддерживаемая этой реализацией
+pub const MIN_VERSION: u8 = 1;
+
+/// Максимальная версия протокола, поддерживаемая этой реализацией
+pub const MAX_VERSION: u8 = FrameVersion::LATEST as u8;
+
+/// Битовая маска возможностей стороны
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Сторона проверяет CRC принятых посылок
+    pub const CRC_VALIDATION: Self = Self(1 << 0);
+
+    /// Данные посылок могут быть произвольными байтами (зарезервировано)
+    pub const BINARY_PAYLOADS: Self = Self(1 << 1);
+
+    /// Подписка на события устройств: сервер может отправлять посылки `Event` без запроса
+    pub const SUBSCRIPTIONS: Self = Self(1 << 2);
+
+    /// Аутентификация клиента (зарезервировано)
+    pub const AUTH: Self = Self(1 << 3);
+
+    /// Возможности, поддерживаемые этой реализацией
+    pub const SUPPORTED: Self = Self(Self::CRC_VALIDATION.0 | Self::SUBSCRIPTIONS.0);
+
+    /// Возможности асинхронных клиента и сервера: посылки `Event` ими не обрабатываются
+    pub const SUPPORTED_ASYNC: Self = Self::CRC_VALIDATION;
+
+    /// Пустой набор возможностей
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Набор возможностей из битовой маски
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Битовая маска набора
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Проверка наличия всех возможностей `other` в наборе
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+/// Параметры стороны, предлагаемые в handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeOffer {
+    /// Поддерживаемые версии протокола
+    pub versions: RangeInclusive<u8>,
+
+    /// Поддерживаемые возможности
+    pub capabilities: Capabilities,
+}
+
+impl Default for HandshakeOffer {
+    fn default() -> Self {
+        Self {
+            versions: MIN_VERSION..=MAX_VERSION,
+            capabilities: Capabilities::SUPPORTED,
+        }
+    }
+}
+
+/// Результат handshake: параметры, о которых договорились стороны
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    /// Версия протокола соединения
+    pub version: FrameVersion,
+
+    /// Возможности, поддерживаемые обеими сторонами
+    pub capabilities: Capabilities,
+}
+
+impl HandshakeOffer {
+    /// Параметры асинхронных клиента и сервера по умолчанию
+    pub fn async_default() -> Self {
+        Self {
+            capabilities: Capabilities::SUPPORTED_ASYNC,
+            ..Self::default()
+        }
+    }
+
+    /// Параметры клиента, использующего устаревший handshake
+    pub fn legacy() -> Self {
+        Self {
+            versions: LEGACY_VERSION..=LEGACY_VERSION,
+            capabilities: Capabilities::empty(),
+        }
+    }
+
+    /// Согласование параметров с предложением другой стороны
+    ///
+    /// Версии за пределами `MIN_VERSION..=MAX_VERSION` этой реализацией не поддерживаются,
+    /// даже если указаны в предложении.
+    pub fn negotiate(&self, remote: &HandshakeOffer) -> Result<Negotiated, ConnectError> {
+        let version = (*self.versions.end())
+            .min(*remote.versions.end())
+            .min(MAX_VERSION);
+        let lowest = (*self.versions.start())
+            .max(*remote.versions.start())
+            .max(MIN_VERSION);
+        let version = match FrameVersion::try_from(version) {
+            Ok(version) if version as u8 >= lowest => version,
+            _ => {
+                return Err(ConnectError::UnsupportedVersion {
+                    local: self.versions.clone(),
+                    remote: remote.versions.clone(),
+                })
+            }
+        };
+        Ok(Negotiated {
+            version,
+            capabilities: self.capabilities & remote.capabilities,
+        })
+    }
+
+    /// Сериализация приветствия клиента
+    pub fn client_hello(&self) -> [u8; 14] {
+        let mut raw_bytes = [0; 14];
+        raw_bytes[..8].copy_from_slice(CLIENT_MAGIC);
+        raw_bytes[8] = *self.versions.start();
+        raw_bytes[9] = *self.versions.end();
+        raw_bytes[10..].copy_from_slice(&self.capabilities.bits().to_be_bytes());
+        raw_bytes
+    }
+
+    /// Разбор приветствия клиента без магической строки
+    pub fn parse_client_hello(raw_bytes: &[u8; 6]) -> Self {
+        Self {
+            versions: raw_bytes[0]..=raw_bytes[1],
+            capabilities: Capabilities::from_bits(u32::from_be_bytes([
+                raw_bytes[2],
+                raw_bytes[3],
+                raw_bytes[4],
+                raw_bytes[5],
+            ])),
+        }
+    }
+
+    /// Сериализация ответа сервера с результатом согласования (`None` - общей версии нет)
+    pub fn server_reply(&self, negotiated: Option<&Negotiated>) -> [u8; 15] {
+        let mut raw_bytes = [0; 15];
+        raw_bytes[..8].copy_from_slice(SERVER_MAGIC);
+        raw_bytes[8] = *self.versions.start();
+        raw_bytes[9] = *self.versions.end();
+        if let Some(negotiated) = negotiated {
+            raw_bytes[10] = negotiated.version as u8;
+            raw_bytes[11..].copy_from_slice(&negotiated.capabilities.bits().to_be_bytes());
+        }
+        raw_bytes
+    }
+
+    /// Разбор ответа сервера без магической строки и проверка его согласованности
+    pub fn parse_server_reply(&self, raw_bytes: &[u8; 7]) -> Result<Negotiated, ConnectError> {
+        let remote = HandshakeOffer {
+            versions: raw_bytes[0]..=raw_bytes[1],
+            capabilities: Capabilities::empty(),
+        };
+        let version = raw_bytes[2];
+        if version == 0 {
+            return Err(ConnectError::UnsupportedVersion {
+                local: self.versions.clone(),
+                remote: remote.versions,
+            });
+        }
+        if !self.versions.contains(&version) || !remote.versions.contains(&version) {
+            return Err(ConnectError::BadHandshake);
+        }
+        let version = FrameVersion::try_from(version).map_err(|_| ConnectError::BadHandshake)?;
+        let capabilities = Capabilities::from_bits(u32::from_be_bytes([
+            raw_bytes[3],
+            raw_bytes[4],
+            raw_bytes[5],
+            raw_bytes[6],
+        ]));
+        Ok(Negotiated {
+            version,
+            capabilities: capabilities & self.capabilities,
+        })
+    }
+}
+
+/// Handshake на стороне клиента
+pub(crate) fn client_handshake<Stream: Read + Write>(
+    stream: &mut Stream,
+    offer: &HandshakeOffer,
+) -> Result<Negotiated, ConnectError> {
+    stream.write_all(&offer.client_hello())?;
+
+    let mut magic = [0; 8];
+    stream.read_exact(&mut magic)?;
+    if &magic != SERVER_MAGIC {
+        return Err(ConnectError::BadHandshake);
+    }
+
+    let mut reply = [0; 7];
+    stream.read_exact(&mut reply)?;
+    offer.parse_server_reply(&reply)
+}
+
+/// Handshake на стороне сервера, поддерживает как расширенный, так и устаревший вариант
+pub(crate) fn server_handshake<Stream: Read + Write>(
+    stream: &mut Stream,
+    offer: &HandshakeOffer,
+) -> Result<Negotiated, ConnectError> {
+    let mut magic = [0; 8];
+    stream.read_exact(&mut magic)?;
+
+    if &magic == CLIENT_MAGIC_LEGACY {
+        let negotiated = offer.negotiate(&HandshakeOffer::legacy())?;
+        stream.write_all(SERVER_MAGIC_LEGACY)?;
+        return Ok(negotiated);
+    }
+
+    if &magic != CLIENT_MAGIC {
+        return Err(ConnectError::BadHandshake);
+    }
+
+    let mut hello = [0; 6];
+    stream.read_exact(&mut hello)?;
+    let remote = HandshakeOffer::parse_client_hello(&hello);
+
+    let negotiated = offer.negotiate(&remote);
+    stream.write_all(&offer.server_reply(negotiated.as_ref().ok()))?;
+    negotiated
+}
+
+/// Асинхронный handshake на стороне клиента
+#[cfg(feature = "async")]
+pub(crate) async fn client_handshake_async<Stream>(
+    stream: &mut Stream,
+    offer: &HandshakeOffer,
+) -> Result<Negotiated, ConnectError>
+where
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    stream.write_all(&offer.client_hello()).await?;
+
+    let mut magic = [0; 8];
+    stream.read_exact(&mut magic).await?;
+    if &magic != SERVER_MAGIC {
+        return Err(ConnectError::BadHandshake);
+    }
+
+    let mut reply = [0; 7];
+    stream.read_exact(&mut reply).await?;
+    offer.parse_server_reply(&reply)
+}
+
+/// Асинхронный handshake на стороне сервера, поддерживает как расширенный, так и устаревший вариант
+#[cfg(feature = "async")]
+pub(crate) async fn server_handshake_async<Stream>(
+    stream: &mut Stream,
+    offer: &HandshakeOffer,
+) -> Result<Negotiated, ConnectError>
+where
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut magic = [0; 8];
+    stream.read_exact(&mut magic).await?;
+
+    if &magic == CLIENT_MAGIC_LEGACY {
+        let negotiated = offer.negotiate(&HandshakeOffer::legacy())?;
+        stream.write_all(SERVER_MAGIC_LEGACY).await?;
+        return Ok(negotiated);
+    }
+
+    if &magic != CLIENT_MAGIC {
+        return Err(ConnectError::BadHandshake);
+    }
+
+    let mut hello = [0; 6];
+    stream.read_exact(&mut hello).await?;
+    let remote = HandshakeOffer::parse_client_hello(&hello);
+
+    let negotiated = offer.negotiate(&remote);
+    stream
+        .write_all(&offer.server_reply(negotiated.as_ref().ok()))
+        .await?;
+    negotiated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Поток, читающий заранее заданные байты и запоминающий записанные
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: Vec<u8>) -> Self {
+            Self {
+                input: Cursor::new(input),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn offer(versions: RangeInclusive<u8>, capabilities: Capabilities) -> HandshakeOffer {
+        HandshakeOffer {
+            versions,
+            capabilities,
+        }
+    }
+
+    #[test]
+    fn test_negotiate_highest_common_version() {
+        let server = offer(
+            1..=MAX_VERSION,
+            Capabilities::CRC_VALIDATION | Capabilities::AUTH,
+        );
+        let client = offer(1..=200, Capabilities::CRC_VALIDATION);
+
+        assert_eq!(
+            server.negotiate(&client).unwrap(),
+            Negotiated {
+                version: FrameVersion::LATEST,
+                capabilities: Capabilities::CRC_VALIDATION,
+            }
+        );
+    }
+
+    #[test]
+    fn test_negotiate_old_client_keeps_old_version() {
+        let server = HandshakeOffer::default();
+        let client = offer(1..=1, Capabilities::SUPPORTED);
+
+        assert_eq!(server.negotiate(&client).unwrap().version, FrameVersion::V1);
+    }
+
+    #[test]
+    fn test_negotiate_no_common_version() {
+        let server = offer(1..=2, Capabilities::SUPPORTED);
+        let client = offer(3..=4, Capabilities::SUPPORTED);
+
+        assert!(matches!(
+            server.negotiate(&client),
+            Err(ConnectError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_server_accepts_legacy_client() {
+        let mut stream = MockStream::new(CLIENT_MAGIC_LEGACY.to_vec());
+
+        let negotiated = server_handshake(&mut stream, &HandshakeOffer::default()).unwrap();
+
+        assert_eq!(negotiated.version as u8, LEGACY_VERSION);
+        assert_eq!(negotiated.capabilities, Capabilities::empty());
+        assert_eq!(stream.output, SERVER_MAGIC_LEGACY);
+    }
+
+    #[test]
+    fn test_extended_handshake_roundtrip() {
+        let client = offer(
+            1..=4,
+            Capabilities::CRC_VALIDATION | Capabilities::SUBSCRIPTIONS,
+        );
+        let server = HandshakeOffer::default();
+
+        let mut server_stream = MockStream::new(client.client_hello().to_vec());
+        let server_side = server_handshake(&mut server_stream, &server).unwrap();
+
+        let mut client_stream = MockStream::new(server_stream.output);
+        let client_side = client_handshake(&mut client_stream, &client).unwrap();
+
+        assert_eq!(server_side, client_side);
+        assert_eq!(client_side.version, FrameVersion::LATEST);
+        assert_eq!(
+            client_side.capabilities,
+            Capabilities::CRC_VALIDATION | Capabilities::SUBSCRIPTIONS
+        );
+    }
+
+    #[test]
+    fn test_extended_handshake_unsupported_version() {
+        let client = offer(200..=201, Capabilities::SUPPORTED);
+        let server = HandshakeOffer::default();
+
+        let mut server_stream = MockStream::new(client.client_hello().to_vec());
+        let server_side = server_handshake(&mut server_stream, &server);
+        assert!(matches!(
+            server_side,
+            Err(ConnectError::UnsupportedVersion { .. })
+        ));
+
+        let mut client_stream = MockStream::new(server_stream.output);
+        match client_handshake(&mut client_stream, &client) {
+            Err(ConnectError::UnsupportedVersion { local, remote }) => {
+                assert_eq!(local, 200..=201);
+                assert_eq!(remote, MIN_VERSION..=MAX_VERSION);
+            }
+            other => panic!("unexpected handshake result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_server_rejects_unknown_magic() {
+        let mut stream = MockStream::new(b"http/1.1".to_vec());
+
+        assert!(matches!(
+            server_handshake(&mut stream, &HandshakeOffer::default()),
+            Err(ConnectError::BadHandshake)
+        ));
+    }
+}
@@ -1,36 +1,933 @@
-use crc16::{State, ARC};
+use crate::iot_error::{DecodeError, DeviceError, PayloadError};
+use crate::iot_spec::{self, FrameVersion, MAX_DATA_LENGTH};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use smart_socket::electrical::ElectricalLimits;
+use smart_socket::energy::EnergyReading;
+use smart_socket::faults::{FaultInjection, RandomFaults};
+use smart_socket::protection::TripEvent;
+use smart_socket::schedule::{TimeOfDay, Weekday};
+use smart_socket::{
+    SmartDevice, SmartDeviceErrorCode, SmartDeviceKind, SmartDevicePowerState, SmartDeviceStatus,
+    TemperatureThresholds,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::{Duration, SystemTime};
 
-pub const CRC_LENGTH: usize = 2;
+pub use crate::iot_spec::CRC_LENGTH;
 
-/// Поддерживаемые команды
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Команды протокола, коды перечислены в модуле `iot_spec`
+///
+/// Коды, не известные этой реализации, принимаются как `CommandType::Unknown`,
+/// чтобы новые команды не нарушали обмен с узлами, которые их не поддерживают:
+/// сервер отвечает на них кодом результата `ResponseStatus::UnknownCommand`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum CommandType {
-    SetPowerOn = 0x01,
-    SetPowerOff = 0x02,
-    GetStatus = 0x03,
+    SetPowerOn,
+    SetPowerOff,
+    GetStatus,
+    /// Получение потребляемой мощности
+    GetPower,
+    /// Переименование устройства
+    Rename,
+    /// Получение показаний счётчика энергии
+    GetEnergy,
+    /// Сброс сбрасываемого счётчика энергии
+    ResetEnergy,
+    /// Получение напряжения, тока и коэффициента мощности
+    GetTelemetry,
+    /// Изменение пределов напряжения и тока
+    SetLimits,
+    /// Сброс неисправности после защитного отключения
+    ResetFault,
+    /// Получение истории защитных отключений
+    GetTripHistory,
+    /// Внесение неисправностей (только для администратора)
+    InjectFault,
+    /// Список устройств сервера
+    ListDevices,
+    /// Добавление устройства с идентификатором из заголовка посылки
+    AddDevice,
+    /// Удаление устройства
+    RemoveDevice,
+    /// Получение температуры и порогов термометра
+    GetTemperature,
+    /// Изменение порогов температуры термометра
+    SetThresholds,
+    /// Создание таймера обратного отсчёта
+    AddTimer,
+    /// Создание недельного расписания
+    AddSchedule,
+    /// Список таймеров и расписаний устройства
+    ListSchedules,
+    /// Удаление таймера или расписания
+    DeleteSchedule,
+    /// Сохранение сцены
+    SaveScene,
+    /// Список сцен
+    ListScenes,
+    /// Применение сцены
+    ApplyScene,
+    /// Сохранение группы устройств
+    SaveGroup,
+    /// Список групп устройств
+    ListGroups,
+    /// Удаление группы устройств
+    DeleteGroup,
+    /// Выполнение команды всеми устройствами группы
+    GroupCommand,
+    /// Подписка на события устройств
+    Subscribe,
+    /// Отмена подписки на события
+    Unsubscribe,
+    /// Событие устройства, отправляемое сервером подписчику без запроса
+    Event,
+    /// Ответ сервера на запрос, который не удалось принять (например, с неверной CRC)
+    ProtocolError,
+    /// Код команды, не известный этой реализации
+    Unknown(u8),
 }
 
-/// Структура посылки
-#[derive(Debug, Clone)]
+impl CommandType {
+    /// Код команды в посылке
+    pub fn code(self) -> u8 {
+        match self {
+            Self::SetPowerOn => 0x01,
+            Self::SetPowerOff => 0x02,
+            Self::GetStatus => 0x03,
+            Self::GetPower => 0x04,
+            Self::Rename => 0x05,
+            Self::GetEnergy => 0x06,
+            Self::ResetEnergy => 0x07,
+            Self::GetTelemetry => 0x08,
+            Self::SetLimits => 0x09,
+            Self::ResetFault => 0x0A,
+            Self::GetTripHistory => 0x0B,
+            Self::InjectFault => 0x0C,
+            Self::ListDevices => 0x10,
+            Self::AddDevice => 0x11,
+            Self::RemoveDevice => 0x12,
+            Self::GetTemperature => 0x20,
+            Self::SetThresholds => 0x21,
+            Self::AddTimer => 0x30,
+            Self::AddSchedule => 0x31,
+            Self::ListSchedules => 0x32,
+            Self::DeleteSchedule => 0x33,
+            Self::SaveScene => 0x40,
+            Self::ListScenes => 0x41,
+            Self::ApplyScene => 0x42,
+            Self::SaveGroup => 0x50,
+            Self::ListGroups => 0x51,
+            Self::DeleteGroup => 0x52,
+            Self::GroupCommand => 0x53,
+            Self::Subscribe => 0x60,
+            Self::Unsubscribe => 0x61,
+            Self::Event => 0x62,
+            Self::ProtocolError => 0xFF,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+/// Известные коды переводятся в соответствующие команды, остальные - в `CommandType::Unknown`
+impl From<u8> for CommandType {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => Self::SetPowerOn,
+            0x02 => Self::SetPowerOff,
+            0x03 => Self::GetStatus,
+            0x04 => Self::GetPower,
+            0x05 => Self::Rename,
+            0x06 => Self::GetEnergy,
+            0x07 => Self::ResetEnergy,
+            0x08 => Self::GetTelemetry,
+            0x09 => Self::SetLimits,
+            0x0A => Self::ResetFault,
+            0x0B => Self::GetTripHistory,
+            0x0C => Self::InjectFault,
+            0x10 => Self::ListDevices,
+            0x11 => Self::AddDevice,
+            0x12 => Self::RemoveDevice,
+            0x20 => Self::GetTemperature,
+            0x21 => Self::SetThresholds,
+            0x30 => Self::AddTimer,
+            0x31 => Self::AddSchedule,
+            0x32 => Self::ListSchedules,
+            0x33 => Self::DeleteSchedule,
+            0x40 => Self::SaveScene,
+            0x41 => Self::ListScenes,
+            0x42 => Self::ApplyScene,
+            0x50 => Self::SaveGroup,
+            0x51 => Self::ListGroups,
+            0x52 => Self::DeleteGroup,
+            0x53 => Self::GroupCommand,
+            0x60 => Self::Subscribe,
+            0x61 => Self::Unsubscribe,
+            0x62 => Self::Event,
+            0xFF => Self::ProtocolError,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// Код результата обработки запроса, передаётся начиная с версии формата 3
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ResponseStatus {
+    /// Запрос выполнен
+    #[default]
+    Ok,
+    /// Некорректный запрос
+    BadRequest,
+    /// Обращение к несуществующему устройству
+    UnknownDevice,
+    /// Команда не поддерживается
+    UnknownCommand,
+    /// Недостаточно прав
+    Unauthorized,
+    /// Неисправность устройства
+    DeviceFault(SmartDeviceErrorCode),
+}
+
+impl ResponseStatus {
+    /// Код результата в посылке
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Ok => 0x00,
+            Self::BadRequest => 0x01,
+            Self::UnknownDevice => 0x02,
+            Self::UnknownCommand => 0x03,
+            Self::Unauthorized => 0x04,
+            Self::DeviceFault(SmartDeviceErrorCode::Overcurrent) => 0x10,
+            Self::DeviceFault(SmartDeviceErrorCode::Overvoltage) => 0x11,
+            Self::DeviceFault(SmartDeviceErrorCode::Overheat) => 0x12,
+            Self::DeviceFault(SmartDeviceErrorCode::Underheat) => 0x13,
+        }
+    }
+
+    /// Результат в виде `Result`: `Err` для всех кодов, кроме `Ok`
+    pub fn into_result(self) -> Result<(), DeviceError> {
+        match self {
+            Self::Ok => Ok(()),
+            Self::BadRequest => Err(DeviceError::BadRequest),
+            Self::UnknownDevice => Err(DeviceError::UnknownDevice),
+            Self::UnknownCommand => Err(DeviceError::UnknownCommand),
+            Self::Unauthorized => Err(DeviceError::Unauthorized),
+            Self::DeviceFault(code) => Err(DeviceError::Fault(code)),
+        }
+    }
+}
+
+impl TryFrom<u8> for ResponseStatus {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::Ok),
+            0x01 => Ok(Self::BadRequest),
+            0x02 => Ok(Self::UnknownDevice),
+            0x03 => Ok(Self::UnknownCommand),
+            0x04 => Ok(Self::Unauthorized),
+            0x10 => Ok(Self::DeviceFault(SmartDeviceErrorCode::Overcurrent)),
+            0x11 => Ok(Self::DeviceFault(SmartDeviceErrorCode::Overvoltage)),
+            0x12 => Ok(Self::DeviceFault(SmartDeviceErrorCode::Overheat)),
+            0x13 => Ok(Self::DeviceFault(SmartDeviceErrorCode::Underheat)),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<DeviceError> for ResponseStatus {
+    fn from(value: DeviceError) -> Self {
+        match value {
+            DeviceError::BadRequest => Self::BadRequest,
+            DeviceError::UnknownDevice => Self::UnknownDevice,
+            DeviceError::UnknownCommand => Self::UnknownCommand,
+            DeviceError::Unauthorized => Self::Unauthorized,
+            DeviceError::Fault(code) => Self::DeviceFault(code),
+        }
+    }
+}
+
+/// Состояние умного устройства, данные ответа на команды управления питанием и `GetStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StatusReport {
+    /// Состояние питания (при неисправности устройство считается выключенным)
+    pub power_state: SmartDevicePowerState,
+    /// Потребляемая мощность, Вт
+    pub power_consumption_w: f32,
+    /// Неисправность, если есть
+    pub fault: Option<SmartDeviceErrorCode>,
+}
+
+/// Потребляемая мощность, данные ответа на `GetPower`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PowerReport {
+    /// Потребляемая мощность, Вт
+    pub power_consumption_w: f32,
+}
+
+/// Показания счётчика энергии, данные ответа на `GetEnergy` и `ResetEnergy`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnergyReport {
+    /// Энергия за всё время, кВт·ч
+    pub total_kwh: f64,
+    /// Энергия с момента сброса, кВт·ч
+    pub since_reset_kwh: f64,
+    /// Момент сброса, секунды от 1970-01-01 UTC
+    pub reset_at: u64,
+    /// Энергия по суткам (UTC), в порядке возрастания дат
+    pub daily: Vec<EnergyBucket>,
+    /// Энергия по месяцам (UTC), в порядке возрастания
+    pub monthly: Vec<EnergyBucket>,
+}
+
+/// Энергия, потреблённая за период
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnergyBucket {
+    /// Период: `ГГГГ-ММ-ДД` для суток, `ГГГГ-ММ` для месяца
+    pub period: String,
+    /// Энергия, кВт·ч
+    pub kwh: f64,
+}
+
+impl From<EnergyReading> for EnergyReport {
+    fn from(reading: EnergyReading) -> Self {
+        let bucket = |period: &dyn fmt::Display, kwh| EnergyBucket {
+            period: period.to_string(),
+            kwh,
+        };
+        Self {
+            total_kwh: reading.total_kwh,
+            since_reset_kwh: reading.since_reset_kwh,
+            reset_at: unix_seconds(reading.reset_at),
+            daily: reading
+                .daily_kwh
+                .iter()
+                .map(|(date, kwh)| bucket(date, *kwh))
+                .collect(),
+            monthly: reading
+                .monthly_kwh
+                .iter()
+                .map(|(month, kwh)| bucket(month, *kwh))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for EnergyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Energy: {:.3} kWh total, {:.3} kWh since reset.",
+            self.total_kwh, self.since_reset_kwh
+        )
+    }
+}
+
+/// Электрические показания розетки, данные ответа на `GetTelemetry` и `SetLimits`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryReport {
+    /// Напряжение сети, В
+    pub voltage_v: f32,
+    /// Ток нагрузки, А
+    pub current_a: f32,
+    /// Коэффициент мощности нагрузки
+    pub power_factor: f32,
+    /// Потребляемая мощность, Вт
+    pub power_consumption_w: f32,
+    /// Пределы напряжения и тока
+    pub limits: ElectricalLimits,
+    /// Неисправность (`Overvoltage` или `Overcurrent` при превышении пределов), если есть
+    pub fault: Option<SmartDeviceErrorCode>,
+}
+
+impl TelemetryReport {
+    /// Показания устройства, `None` - устройство не измеряет напряжение и ток
+    pub fn from_device(device: &dyn SmartDevice) -> Option<Self> {
+        let readings = device.get_electrical()?;
+        let fault = match device.get_status() {
+            SmartDeviceStatus::PowerState(_) => None,
+            SmartDeviceStatus::Malfunction(code) => Some(code),
+        };
+        Some(Self {
+            voltage_v: readings.voltage_v,
+            current_a: readings.current_a,
+            power_factor: readings.power_factor,
+            power_consumption_w: device.get_power_consumption(),
+            limits: device.get_electrical_limits()?,
+            fault,
+        })
+    }
+}
+
+impl fmt::Display for TelemetryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Voltage: {:.1} V, current: {:.2} A, power factor: {:.2} (limits {} V, {} A).",
+            self.voltage_v,
+            self.current_a,
+            self.power_factor,
+            self.limits.max_voltage_v,
+            self.limits.max_current_a
+        )?;
+        if let Some(fault) = self.fault {
+            write!(f, " Fault: {fault}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Событие защитного отключения, элемент ответа на `GetTripHistory`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TripRecord {
+    /// Момент отключения, секунды от 1970-01-01 UTC
+    pub at: u64,
+    /// Причина отключения
+    pub fault: SmartDeviceErrorCode,
+    /// Напряжение в момент отключения, В
+    pub voltage_v: f32,
+    /// Ток в момент отключения, А
+    pub current_a: f32,
+}
+
+impl From<TripEvent> for TripRecord {
+    fn from(event: TripEvent) -> Self {
+        Self {
+            at: unix_seconds(event.at),
+            fault: event.fault,
+            voltage_v: event.voltage_v,
+            current_a: event.current_a,
+        }
+    }
+}
+
+/// Данные запроса `InjectFault`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultInjectionRequest {
+    /// Токен администратора
+    pub token: String,
+    /// Действие
+    pub action: FaultAction,
+}
+
+/// Действие по внесению неисправностей, длительности - в секундах
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FaultAction {
+    /// Немедленная неисправность на `duration_s` (`null` - до отмены)
+    Inject {
+        fault: SmartDeviceErrorCode,
+        duration_s: Option<f64>,
+    },
+    /// Неисправность через `delay_s` на `duration_s` (`null` - до отмены)
+    Schedule {
+        delay_s: f64,
+        fault: SmartDeviceErrorCode,
+        duration_s: Option<f64>,
+    },
+    /// Случайные неисправности с вероятностью `probability` за секунду
+    /// (пустой список `faults` - любые)
+    Random {
+        probability: f64,
+        duration_s: f64,
+        faults: Vec<SmartDeviceErrorCode>,
+    },
+    /// Отключение случайных неисправностей
+    StopRandom,
+    /// Отмена всех внесённых неисправностей
+    Clear,
+}
+
+/// Недопустимые длительности и вероятности - `DeviceError::BadRequest`
+impl TryFrom<FaultAction> for FaultInjection {
+    type Error = DeviceError;
+
+    fn try_from(action: FaultAction) -> Result<Self, Self::Error> {
+        let seconds =
+            |secs: f64| Duration::try_from_secs_f64(secs).map_err(|_| DeviceError::BadRequest);
+        Ok(match action {
+            FaultAction::Inject { fault, duration_s } => FaultInjection::Inject {
+                fault,
+                duration: duration_s.map(seconds).transpose()?,
+            },
+            FaultAction::Schedule {
+                delay_s,
+                fault,
+                duration_s,
+            } => FaultInjection::Schedule {
+                delay: seconds(delay_s)?,
+                fault,
+                duration: duration_s.map(seconds).transpose()?,
+            },
+            FaultAction::Random {
+                probability,
+                duration_s,
+                faults,
+            } => {
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(DeviceError::BadRequest);
+                }
+                FaultInjection::Random(Some(RandomFaults {
+                    probability,
+                    duration: seconds(duration_s)?,
+                    faults,
+                }))
+            }
+            FaultAction::StopRandom => FaultInjection::Random(None),
+            FaultAction::Clear => FaultInjection::Clear,
+        })
+    }
+}
+
+/// Таймер обратного отсчёта, данные запроса `AddTimer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewTimer {
+    /// Время до срабатывания, секунды
+    pub delay_s: u64,
+    /// Устанавливаемое состояние питания
+    pub action: SmartDevicePowerState,
+}
+
+/// Таймер или расписание, данные ответа на `AddTimer` и `AddSchedule`,
+/// элемент ответа на `ListSchedules`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleInfo {
+    /// Идентификатор таймера или расписания на сервере
+    pub id: u32,
+    /// Идентификатор устройства
+    pub device_id: u8,
+    /// Вид: таймер или недельное расписание
+    pub kind: ScheduleKind,
+    /// Устанавливаемое состояние питания
+    pub action: SmartDevicePowerState,
+    /// Ближайшее срабатывание, секунды от 1970-01-01 UTC
+    pub next_at: u64,
+}
+
+/// Вид записи планировщика
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleKind {
+    /// Однократный таймер обратного отсчёта
+    Timer,
+    /// Недельное расписание (время по UTC)
+    Weekly { days: Vec<Weekday>, at: TimeOfDay },
+}
+
+impl fmt::Display for ScheduleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}: {:?} ", self.id, self.action)?;
+        match &self.kind {
+            ScheduleKind::Timer => write!(f, "by timer"),
+            ScheduleKind::Weekly { days, at } => write!(f, "at {at} UTC on {days:?}"),
+        }
+    }
+}
+
+/// Сцена: состояния питания, устанавливаемые устройствам одновременно.
+/// Данные запроса `SaveScene`, элемент ответа на `ListScenes`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scene {
+    /// Имя сцены
+    pub name: String,
+    /// Состояние питания для каждого устройства сцены
+    pub states: BTreeMap<u8, SmartDevicePowerState>,
+}
+
+/// Результат применения сцены, данные ответа на `ApplyScene`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SceneReport {
+    /// Имя сцены
+    pub name: String,
+    /// Результаты по устройствам в порядке возрастания идентификаторов
+    pub results: Vec<SceneResult>,
+}
+
+/// Результат применения сцены к одному устройству
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SceneResult {
+    /// Идентификатор устройства
+    pub device_id: u8,
+    /// Результат
+    pub outcome: SceneOutcome,
+}
+
+/// Результат применения сцены к устройству
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SceneOutcome {
+    /// Состояние питания установлено
+    Applied,
+    /// Устройство неисправно
+    Fault(SmartDeviceErrorCode),
+    /// Устройства нет на сервере
+    UnknownDevice,
+    /// Устройство не управляет питанием
+    Unsupported,
+}
+
+impl SceneReport {
+    /// Сцена применена ко всем устройствам
+    pub fn is_complete(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| result.outcome == SceneOutcome::Applied)
+    }
+}
+
+impl fmt::Display for SceneReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scene '{}':", self.name)?;
+        for result in &self.results {
+            write!(f, " #{}: ", result.device_id)?;
+            match result.outcome {
+                SceneOutcome::Applied => write!(f, "applied.")?,
+                SceneOutcome::Fault(fault) => write!(f, "{fault}")?,
+                SceneOutcome::UnknownDevice => write!(f, "unknown device.")?,
+                SceneOutcome::Unsupported => write!(f, "unsupported.")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Группа устройств (например, комната), данные запроса `SaveGroup`, элемент ответа на `ListGroups`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    /// Имя группы
+    pub name: String,
+    /// Идентификаторы устройств группы
+    pub devices: BTreeSet<u8>,
+}
+
+/// Команда, адресованная группе устройств, данные запроса `GroupCommand`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupRequest {
+    /// Имя группы
+    pub group: String,
+    /// Код команды, выполняемой каждым устройством группы
+    pub command: u8,
+    /// Данные запроса, передаваемые каждому устройству
+    pub data: String,
+}
+
+impl GroupRequest {
+    /// Запрос на выполнение команды `command` с данными `data` устройствами группы `group`
+    pub fn new(group: &str, command: CommandType, data: String) -> Self {
+        Self {
+            group: group.to_string(),
+            command: command.code(),
+            data,
+        }
+    }
+
+    /// Команда, выполняемая устройствами группы
+    pub fn get_command_type(&self) -> CommandType {
+        CommandType::from(self.command)
+    }
+}
+
+/// Результаты выполнения команды устройствами группы, данные ответа на `GroupCommand`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupReport {
+    /// Имя группы
+    pub group: String,
+    /// Результаты по устройствам в порядке возрастания идентификаторов
+    pub results: Vec<GroupResult>,
+}
+
+/// Ответ одного устройства группы
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupResult {
+    /// Идентификатор устройства
+    pub device_id: u8,
+    /// Код результата (см. `ResponseStatus`)
+    pub status: u8,
+    /// Данные ответа устройства или описание ошибки
+    pub data: String,
+}
+
+impl GroupResult {
+    /// Результат из ответа на команду, адресованную устройству группы
+    pub fn from_response(response: &IotMessage) -> Self {
+        Self {
+            device_id: response.get_id(),
+            status: response.get_status().code(),
+            data: response.get_message_data(),
+        }
+    }
+
+    /// Код результата; неизвестный код возвращается как ошибка
+    pub fn get_status(&self) -> Result<ResponseStatus, DecodeError> {
+        ResponseStatus::try_from(self.status).map_err(DecodeError::UnknownStatus)
+    }
+
+    /// Разбор данных ответа устройства
+    pub fn get_payload<P: DeserializeOwned>(&self) -> Result<P, PayloadError> {
+        Ok(serde_json::from_str(&self.data)?)
+    }
+}
+
+impl GroupReport {
+    /// Команда выполнена всеми устройствами группы
+    pub fn is_complete(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| result.status == ResponseStatus::Ok.code())
+    }
+}
+
+impl fmt::Display for GroupReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Group '{}':", self.group)?;
+        for result in &self.results {
+            write!(f, " #{}: ", result.device_id)?;
+            match result.get_status().map(ResponseStatus::into_result) {
+                Ok(Ok(())) => write!(f, "ok.")?,
+                Ok(Err(e)) => write!(f, "{e}.")?,
+                Err(e) => write!(f, "{e}.")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Подписка на события устройств, данные запроса `Subscribe`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Устройства, о событиях которых сообщается; пустой набор - все устройства
+    #[serde(default)]
+    pub devices: BTreeSet<u8>,
+    /// Порог мощности, Вт, о пересечении которого сообщается (`None` - не сообщать)
+    #[serde(default)]
+    pub power_threshold_w: Option<f32>,
+}
+
+impl Subscription {
+    /// Сообщается ли о событиях устройства `device_id`
+    pub fn includes(&self, device_id: u8) -> bool {
+        self.devices.is_empty() || self.devices.contains(&device_id)
+    }
+}
+
+/// Событие устройства, данные посылки `Event`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeviceEvent {
+    /// Идентификатор устройства
+    pub device_id: u8,
+    /// Момент обнаружения события, секунды с 1970-01-01 UTC
+    pub at: u64,
+    /// Событие
+    pub kind: DeviceEventKind,
+}
+
+/// Вид события устройства
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DeviceEventKind {
+    /// Изменилось состояние питания
+    PowerState(SmartDevicePowerState),
+    /// Устройство перешло в неисправное состояние
+    Malfunction(SmartDeviceErrorCode),
+    /// Мощность превысила порог подписки
+    PowerAbove { threshold_w: f32, power_w: f32 },
+    /// Мощность опустилась до порога подписки или ниже
+    PowerBelow { threshold_w: f32, power_w: f32 },
+}
+
+impl fmt::Display for DeviceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}: ", self.device_id)?;
+        match self.kind {
+            DeviceEventKind::PowerState(state) => write!(f, "{state}"),
+            DeviceEventKind::Malfunction(fault) => write!(f, "{fault}"),
+            DeviceEventKind::PowerAbove {
+                threshold_w,
+                power_w,
+            } => write!(f, "power {power_w:.1} W is above {threshold_w:.1} W."),
+            DeviceEventKind::PowerBelow {
+                threshold_w,
+                power_w,
+            } => write!(f, "power {power_w:.1} W is not above {threshold_w:.1} W."),
+        }
+    }
+}
+
+/// Показания термометра, данные ответа на `GetTemperature` и `SetThresholds`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureReport {
+    /// Текущая температура, °C
+    pub temperature_c: f32,
+    /// Пороги температуры
+    pub thresholds: TemperatureThresholds,
+    /// Неисправность (`Overheat` или `Underheat` при выходе за пороги), если есть
+    pub fault: Option<SmartDeviceErrorCode>,
+}
+
+impl TemperatureReport {
+    /// Показания устройства, `None` - устройство не измеряет температуру
+    pub fn from_device(device: &dyn SmartDevice) -> Option<Self> {
+        let fault = match device.get_status() {
+            SmartDeviceStatus::PowerState(_) => None,
+            SmartDeviceStatus::Malfunction(code) => Some(code),
+        };
+        Some(Self {
+            temperature_c: device.get_temperature()?,
+            thresholds: device.get_temperature_thresholds()?,
+            fault,
+        })
+    }
+}
+
+impl fmt::Display for TemperatureReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Temperature: {} °C (thresholds {}..{} °C).",
+            self.temperature_c, self.thresholds.low_c, self.thresholds.high_c
+        )?;
+        if let Some(fault) = self.fault {
+            write!(f, " Fault: {fault}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Описание устройства, элемент ответа на `ListDevices`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// Идентификатор устройства
+    pub id: u8,
+    /// Вид устройства
+    pub kind: SmartDeviceKind,
+    /// Имя устройства
+    pub name: String,
+}
+
+impl From<&dyn SmartDevice> for DeviceInfo {
+    fn from(device: &dyn SmartDevice) -> Self {
+        Self {
+            id: device.get_id(),
+            kind: device.get_kind(),
+            name: device.get_name().to_string(),
+        }
+    }
+}
+
+/// Новое устройство, данные запроса `AddDevice`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewDevice {
+    /// Вид устройства
+    pub kind: SmartDeviceKind,
+    /// Имя устройства
+    pub name: String,
+}
+
+impl From<&dyn SmartDevice> for StatusReport {
+    fn from(device: &dyn SmartDevice) -> Self {
+        let (power_state, fault) = match device.get_status() {
+            SmartDeviceStatus::PowerState(state) => (state, None),
+            SmartDeviceStatus::Malfunction(code) => (SmartDevicePowerState::Disabled, Some(code)),
+        };
+        Self {
+            power_state,
+            power_consumption_w: device.get_power_consumption(),
+            fault,
+        }
+    }
+}
+
+impl fmt::Display for StatusReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Power consumption: {} W.",
+            self.power_state, self.power_consumption_w
+        )?;
+        if let Some(fault) = self.fault {
+            write!(f, " Fault: {fault}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Момент времени в секундах от 1970-01-01 UTC (более ранние моменты - ноль)
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Структура посылки, формат описан в модуле `iot_spec`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IotMessage {
     id: u8,
     command: CommandType,
+    seq: u16,
+    status: ResponseStatus,
     message_data: String,
-    data_length: u16,
-    crc: u16,
 }
 
 impl IotMessage {
+    /// Создание посылки с данными заведомо небольшой длины
+    ///
+    /// # Panics
+    /// Если длина `data` в байтах превышает `MAX_DATA_LENGTH`; для данных,
+    /// заданных пользователем, используйте `try_new`.
     pub fn new(device_id: u8, command: CommandType, data: String) -> Self {
-        let mut temp = IotMessage {
+        Self::try_new(device_id, command, data).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Создание посылки; данные длиннее `MAX_DATA_LENGTH` байт отклоняются
+    pub fn try_new(
+        device_id: u8,
+        command: CommandType,
+        data: String,
+    ) -> Result<Self, PayloadError> {
+        if data.len() > MAX_DATA_LENGTH {
+            return Err(PayloadError::TooLong(data.len()));
+        }
+        Ok(IotMessage {
             id: device_id,
             command,
-            data_length: data.trim().len() as u16,
+            seq: 0,
+            status: ResponseStatus::Ok,
             message_data: data,
-            crc: 0,
-        };
-        temp.crc = temp.calculate_crc();
-        temp
+        })
+    }
+
+    /// Создание посылки с типизированными данными заведомо небольшой длины, сериализованными в JSON
+    ///
+    /// # Panics
+    /// Если `payload` не сериализуется в JSON или результат длиннее `MAX_DATA_LENGTH`;
+    /// для данных, заданных пользователем, используйте `try_with_payload`.
+    pub fn with_payload<P: Serialize + ?Sized>(
+        device_id: u8,
+        command: CommandType,
+        payload: &P,
+    ) -> Self {
+        Self::try_with_payload(device_id, command, payload).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Создание посылки с типизированными данными, сериализованными в JSON;
+    /// данные, которые не сериализуются или длиннее `MAX_DATA_LENGTH` байт, отклоняются
+    pub fn try_with_payload<P: Serialize + ?Sized>(
+        device_id: u8,
+        command: CommandType,
+        payload: &P,
+    ) -> Result<Self, PayloadError> {
+        Self::try_new(device_id, command, serde_json::to_string(payload)?)
+    }
+
+    /// Посылка с заданным номером запроса
+    pub fn with_seq(mut self, seq: u16) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Посылка с заданным кодом результата
+    pub fn with_status(mut self, status: ResponseStatus) -> Self {
+        self.status = status;
+        self
     }
 
     /// Получение вида команды
@@ -43,78 +940,132 @@ impl IotMessage {
         self.message_data.clone()
     }
 
+    /// Разбор типизированных данных посылки
+    pub fn get_payload<P: DeserializeOwned>(&self) -> Result<P, PayloadError> {
+        Ok(serde_json::from_str(&self.message_data)?)
+    }
+
     /// Получение идентификатора устройства
     pub fn get_id(&self) -> u8 {
         self.id
     }
 
-    /// Расчёт CRC16 по алгоритму ARC
-    pub fn calculate_crc(&self) -> u16 {
-        let mut state = State::<ARC>::new();
-        state.update(&[self.id]);
-        state.update(&[self.command as u8]);
+    /// Получение номера запроса (`0` - посылка не сопоставлена с запросом)
+    pub fn get_seq(&self) -> u16 {
+        self.seq
+    }
 
-        state.update(&[self.data_length.to_be_bytes()[0]]);
-        state.update(&[self.data_length.to_be_bytes()[1]]);
-        state.update(self.message_data.as_bytes());
+    /// Получение кода результата (в версиях формата 1 и 2 всегда `ResponseStatus::Ok`)
+    pub fn get_status(&self) -> ResponseStatus {
+        self.status
+    }
 
-        state.get()
+    /// Расчёт CRC16 по алгоритму ARC для посылки в формате `version`
+    pub fn calculate_crc(&self, version: FrameVersion) -> u16 {
+        iot_spec::crc(&self.encode_without_crc(version))
     }
 
-    /// Сериализация сообщения в "сырые" байты
+    /// Сериализация сообщения в "сырые" байты в формате версии 1
     pub fn serialize_to_raw_byte_data(self) -> Vec<u8> {
-        let mut raw_bytes = vec![
-            self.id,
-            self.command as u8,
-            self.data_length.to_be_bytes()[0],
-            self.data_length.to_be_bytes()[1],
-        ];
+        self.encode(FrameVersion::V1)
+    }
 
-        if self.data_length > 0 {
-            raw_bytes.append(&mut self.message_data.as_bytes().to_vec());
-        } else {
-            raw_bytes.push(0);
-        }
+    /// Сериализация сообщения в "сырые" байты в формате `version`
+    pub fn encode(&self, version: FrameVersion) -> Vec<u8> {
+        let mut raw_bytes = self.encode_without_crc(version);
+        let crc = iot_spec::crc(&raw_bytes);
+        raw_bytes.extend_from_slice(&crc.to_be_bytes());
+        raw_bytes
+    }
 
-        raw_bytes.append(&mut self.crc.to_be_bytes().to_vec());
+    /// Заголовок и данные посылки - всё, по чему считается CRC
+    fn encode_without_crc(&self, version: FrameVersion) -> Vec<u8> {
+        let mut raw_bytes =
+            Vec::with_capacity(version.header_length() + self.message_data.len() + CRC_LENGTH);
+        raw_bytes.push(self.id);
+        raw_bytes.push(self.command.code());
+        if version >= FrameVersion::V2 {
+            raw_bytes.extend_from_slice(&self.seq.to_be_bytes());
+        }
+        if version >= FrameVersion::V3 {
+            raw_bytes.push(self.status.code());
+        }
+        raw_bytes.extend_from_slice(&(self.message_data.len() as u16).to_be_bytes());
+        raw_bytes.extend_from_slice(self.message_data.as_bytes());
         raw_bytes
     }
 
-    /// Десериализация сообщения из "сырых" байт
-    pub fn deserialize_from_raw_byte_data(raw_bytes: Vec<u8>) -> Option<IotMessage> {
-        let id = raw_bytes[0];
-        let command = match raw_bytes[1] {
-            1 => CommandType::SetPowerOn,
-            2 => CommandType::SetPowerOff,
-            3 => CommandType::GetStatus,
-            _ => return None,
+    /// Разбор посылки в формате `version` из "сырых" байт.
+    ///
+    /// Проверяет длину заголовка и данных, CRC, код результата и кодировку данных,
+    /// поэтому пригоден для данных, полученных из сети: на любом входе возвращает ошибку, а не паникует.
+    pub fn decode(raw_bytes: &[u8], version: FrameVersion) -> Result<Self, DecodeError> {
+        let header_length = version.header_length();
+        if raw_bytes.len() < header_length {
+            return Err(DecodeError::TruncatedHeader {
+                length: raw_bytes.len(),
+            });
+        }
+
+        let (header, rest) = raw_bytes.split_at(header_length);
+        let data_length = version.data_length(header);
+
+        if rest.len() < data_length + CRC_LENGTH {
+            return Err(DecodeError::LengthMismatch {
+                declared: data_length,
+                available: rest.len().saturating_sub(CRC_LENGTH),
+            });
+        }
+
+        let (data, rest) = rest.split_at(data_length);
+        let (crc, trailing) = rest.split_at(CRC_LENGTH);
+
+        if !trailing.is_empty() {
+            return Err(DecodeError::TrailingBytes(trailing.len()));
+        }
+
+        // CRC проверяется до разбора содержимого: искажённая посылка - это `BadCrc`,
+        // даже если искажение сделало код результата или данные некорректными
+        let received = u16::from_be_bytes([crc[0], crc[1]]);
+        let expected = iot_spec::crc(&raw_bytes[..header_length + data_length]);
+        if received != expected {
+            return Err(DecodeError::BadCrc { expected, received });
+        }
+
+        let command = CommandType::from(header[1]);
+
+        let status = match version {
+            FrameVersion::V1 | FrameVersion::V2 => ResponseStatus::Ok,
+            FrameVersion::V3 => {
+                ResponseStatus::try_from(header[4]).map_err(DecodeError::UnknownStatus)?
+            }
         };
-        let data_length = u16::from_be_bytes([raw_bytes[2], raw_bytes[3]]);
 
-        let message = IotMessage {
-            id,
-            command,
-            data_length,
-            message_data: String::from_utf8_lossy(&raw_bytes[4..(4 + data_length as usize)])
-                .to_string(),
-            crc: u16::from_be_bytes([
-                raw_bytes[4 + data_length as usize],
-                raw_bytes[4 + data_length as usize + 1],
-            ]),
+        let message_data = std::str::from_utf8(data)
+            .map_err(|_| DecodeError::InvalidUtf8)?
+            .to_string();
+
+        let seq = match version {
+            FrameVersion::V1 => 0,
+            FrameVersion::V2 | FrameVersion::V3 => u16::from_be_bytes([header[2], header[3]]),
         };
 
-        Some(message)
+        Ok(IotMessage {
+            id: header[0],
+            command,
+            seq,
+            status,
+            message_data,
+        })
     }
 }
 
-/// Реализация оператора равенства для IotMessage
-impl PartialEq for IotMessage {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-            && self.command == other.command
-            && self.data_length == other.data_length
-            && self.message_data == other.message_data
-            && self.crc == other.crc
+/// Разбор посылки в формате версии 1 из "сырых" байт, см. `IotMessage::decode`.
+impl TryFrom<&[u8]> for IotMessage {
+    type Error = DecodeError;
+
+    fn try_from(raw_bytes: &[u8]) -> Result<Self, Self::Error> {
+        Self::decode(raw_bytes, FrameVersion::V1)
     }
 }
 
@@ -127,7 +1078,7 @@ mod tests {
     #[test]
     fn test_crc() {
         let command = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
-        assert_eq!(command.crc, 0x340E);
+        assert_eq!(command.calculate_crc(FrameVersion::V1), 0x340E);
     }
 
     /// Проверка сериализации
@@ -146,12 +1097,365 @@ mod tests {
         let command = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
         let raw_bytes = vec![1, 1, 0, 4, 116, 101, 115, 116, 52, 14];
 
-        let the_same_command = IotMessage::deserialize_from_raw_byte_data(raw_bytes);
+        let the_same_command = IotMessage::try_from(raw_bytes.as_slice());
+
+        assert_eq!(the_same_command, Ok(command));
+    }
+
+    /// Проверка обнаружения искажённой посылки
+    #[test]
+    fn test_corrupted_crc() {
+        let raw_bytes = [1, 1, 0, 4, 116, 101, 120, 116, 52, 14];
+
+        assert!(matches!(
+            IotMessage::try_from(raw_bytes.as_slice()),
+            Err(DecodeError::BadCrc { .. })
+        ));
+    }
+
+    /// Искажение кода результата или данных обнаруживается по CRC, а не при разборе
+    #[test]
+    fn test_corruption_is_reported_as_bad_crc() {
+        let message = IotMessage::new(1, CommandType::GetStatus, "test".to_string())
+            .with_status(ResponseStatus::BadRequest);
+        let raw_bytes = message.encode(FrameVersion::V3);
+
+        let mut bad_status = raw_bytes.clone();
+        bad_status[4] = 0x0F;
+        let mut bad_data = raw_bytes;
+        bad_data[7] = 0xFF;
+        for corrupted in [bad_status, bad_data] {
+            assert!(matches!(
+                IotMessage::decode(&corrupted, FrameVersion::V3),
+                Err(DecodeError::BadCrc { .. })
+            ));
+        }
+    }
 
-        assert_eq!(the_same_command.is_some(), true);
+    /// Проверка ошибок разбора некорректных посылок
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(
+            IotMessage::try_from([1, 1, 0].as_slice()),
+            Err(DecodeError::TruncatedHeader { length: 3 })
+        );
+        assert_eq!(
+            IotMessage::try_from([1, 1, 0xFF, 0xFF, 116, 52, 14].as_slice()),
+            Err(DecodeError::LengthMismatch {
+                declared: 0xFFFF,
+                available: 1
+            })
+        );
+        assert_eq!(
+            IotMessage::try_from([1, 1, 0, 4, 116, 101, 115, 116, 52, 14, 0].as_slice()),
+            Err(DecodeError::TrailingBytes(1))
+        );
+        let mut raw_bytes = vec![1, 1, 0, 1, 0x0F, 0, 0];
+        raw_bytes.extend_from_slice(&iot_spec::crc(&raw_bytes).to_be_bytes());
+        assert_eq!(
+            IotMessage::decode(&raw_bytes, FrameVersion::V3),
+            Err(DecodeError::UnknownStatus(0x0F))
+        );
+
+        let mut raw_bytes = vec![1, 1, 0, 2, 0xC3, 0x28];
+        raw_bytes.extend_from_slice(&iot_spec::crc(&raw_bytes).to_be_bytes());
+        assert_eq!(
+            IotMessage::try_from(raw_bytes.as_slice()),
+            Err(DecodeError::InvalidUtf8)
+        );
+    }
 
-        if let Some(message) = the_same_command {
-            assert_eq!(message, command);
+    /// Слишком длинные данные отклоняются без паники
+    #[test]
+    fn test_oversized_payload_is_rejected() {
+        let data = "x".repeat(MAX_DATA_LENGTH);
+        assert!(IotMessage::try_new(47, CommandType::Rename, data.clone()).is_ok());
+        assert!(matches!(
+            IotMessage::try_new(47, CommandType::Rename, data.clone() + "x"),
+            Err(PayloadError::TooLong(length)) if length == MAX_DATA_LENGTH + 1
+        ));
+        // Кавычки JSON удлиняют данные
+        assert!(matches!(
+            IotMessage::try_with_payload(47, CommandType::Rename, &data),
+            Err(PayloadError::TooLong(_))
+        ));
+    }
+
+    /// Все коды результата переводятся в байт и обратно без потерь
+    #[test]
+    fn test_response_status_codes() {
+        for code in 0..=u8::MAX {
+            if let Ok(status) = ResponseStatus::try_from(code) {
+                assert_eq!(status.code(), code);
+                if let Err(e) = status.into_result() {
+                    assert_eq!(ResponseStatus::from(e), status);
+                }
+            }
+        }
+        assert_eq!(ResponseStatus::Ok.into_result(), Ok(()));
+    }
+
+    /// Неизвестный код команды принимается и передаётся дальше без изменений
+    #[test]
+    fn test_unknown_command_roundtrip() {
+        let message = IotMessage::new(1, CommandType::Unknown(0x7E), "t".to_string());
+        let raw_bytes = message.encode(FrameVersion::V1);
+
+        assert_eq!(raw_bytes[1], 0x7E);
+        assert_eq!(IotMessage::try_from(raw_bytes.as_slice()), Ok(message));
+
+        for code in 0..=u8::MAX {
+            assert_eq!(CommandType::from(code).code(), code);
+        }
+    }
+
+    /// Типизированные данные передаются без потерь
+    #[test]
+    fn test_status_report_payload() {
+        let report = StatusReport {
+            power_state: SmartDevicePowerState::Enabled,
+            power_consumption_w: 12.5,
+            fault: Some(SmartDeviceErrorCode::Overheat),
+        };
+        let message = IotMessage::with_payload(47, CommandType::GetStatus, &report);
+
+        assert_eq!(
+            message.get_message_data(),
+            r#"{"power_state":"Enabled","power_consumption_w":12.5,"fault":"Overheat"}"#
+        );
+        assert_eq!(message.get_payload::<StatusReport>().unwrap(), report);
+        assert!(
+            IotMessage::new(47, CommandType::GetStatus, "Dummy".to_string())
+                .get_payload::<StatusReport>()
+                .is_err()
+        );
+    }
+
+    /// Периоды счётчиков энергии передаются строками дат
+    #[test]
+    fn test_energy_report_from_reading() {
+        use smart_socket::clock::Date;
+        use smart_socket::energy::Month;
+        use std::time::Duration;
+
+        let date = Date {
+            year: 2024,
+            month: 2,
+            day: 29,
+        };
+        let reading = EnergyReading {
+            total_kwh: 3.5,
+            since_reset_kwh: 1.25,
+            reset_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_164_800),
+            daily_kwh: vec![(date, 1.25)],
+            monthly_kwh: vec![(Month::from(date), 3.5)],
+        };
+        let report = EnergyReport::from(reading);
+
+        assert_eq!(report.reset_at, 1_709_164_800);
+        assert_eq!(report.daily[0].period, "2024-02-29");
+        assert_eq!(report.monthly[0].period, "2024-02");
+        let message = IotMessage::with_payload(47, CommandType::GetEnergy, &report);
+        assert_eq!(message.get_payload::<EnergyReport>().unwrap(), report);
+    }
+
+    #[test]
+    fn test_fault_action_payload() {
+        let request: FaultInjectionRequest = serde_json::from_str(
+            r#"{"token":"secret","action":{"type":"inject","fault":"Overheat","duration_s":1.5}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.token, "secret");
+        assert_eq!(
+            FaultInjection::try_from(request.action),
+            Ok(FaultInjection::Inject {
+                fault: SmartDeviceErrorCode::Overheat,
+                duration: Some(Duration::from_millis(1500)),
+            })
+        );
+
+        let invalid = [
+            FaultAction::Schedule {
+                delay_s: -1.0,
+                fault: SmartDeviceErrorCode::Overcurrent,
+                duration_s: None,
+            },
+            FaultAction::Random {
+                probability: 1.5,
+                duration_s: 1.0,
+                faults: Vec::new(),
+            },
+        ];
+        for action in invalid {
+            assert_eq!(
+                FaultInjection::try_from(action),
+                Err(DeviceError::BadRequest)
+            );
+        }
+    }
+
+    #[test]
+    fn test_scene_payload() {
+        let scene = Scene {
+            name: String::from("Leaving home"),
+            states: BTreeMap::from([
+                (47, SmartDevicePowerState::Disabled),
+                (50, SmartDevicePowerState::Enabled),
+            ]),
+        };
+        let message = IotMessage::with_payload(0, CommandType::SaveScene, &scene);
+        assert_eq!(
+            message.get_message_data(),
+            r#"{"name":"Leaving home","states":{"47":"Disabled","50":"Enabled"}}"#
+        );
+        assert_eq!(message.get_payload::<Scene>().unwrap(), scene);
+
+        let report = SceneReport {
+            name: scene.name,
+            results: vec![SceneResult {
+                device_id: 47,
+                outcome: SceneOutcome::Fault(SmartDeviceErrorCode::Overcurrent),
+            }],
+        };
+        assert!(!report.is_complete());
+        let message = IotMessage::with_payload(0, CommandType::ApplyScene, &report);
+        assert_eq!(message.get_payload::<SceneReport>().unwrap(), report);
+    }
+
+    #[test]
+    fn test_group_payload() {
+        let request = GroupRequest::new("kitchen", CommandType::SetPowerOff, String::new());
+        let message = IotMessage::with_payload(0, CommandType::GroupCommand, &request);
+        assert_eq!(
+            message.get_message_data(),
+            r#"{"group":"kitchen","command":2,"data":""}"#
+        );
+        let request: GroupRequest = message.get_payload().unwrap();
+        assert_eq!(request.get_command_type(), CommandType::SetPowerOff);
+
+        let status = StatusReport {
+            power_state: SmartDevicePowerState::Disabled,
+            power_consumption_w: 0.0,
+            fault: None,
+        };
+        let fault = DeviceError::Fault(SmartDeviceErrorCode::Overcurrent);
+        let report = GroupReport {
+            group: request.group,
+            results: vec![
+                GroupResult::from_response(&IotMessage::with_payload(
+                    47,
+                    CommandType::SetPowerOff,
+                    &status,
+                )),
+                GroupResult::from_response(
+                    &IotMessage::new(48, CommandType::SetPowerOff, fault.to_string())
+                        .with_status(fault.into()),
+                ),
+            ],
+        };
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.results[0].get_payload::<StatusReport>().unwrap(),
+            status
+        );
+        assert_eq!(
+            report.results[1].get_status(),
+            Ok(ResponseStatus::DeviceFault(
+                SmartDeviceErrorCode::Overcurrent
+            ))
+        );
+        let message = IotMessage::with_payload(0, CommandType::GroupCommand, &report);
+        assert_eq!(message.get_payload::<GroupReport>().unwrap(), report);
+    }
+
+    #[test]
+    fn test_event_payload() {
+        let subscription: Subscription =
+            serde_json::from_str(r#"{"power_threshold_w":100.0}"#).unwrap();
+        assert!(subscription.includes(47));
+        let subscription = Subscription {
+            devices: BTreeSet::from([48]),
+            ..subscription
+        };
+        assert!(!subscription.includes(47));
+
+        let event = DeviceEvent {
+            device_id: 47,
+            at: 1_792_132_200,
+            kind: DeviceEventKind::Malfunction(SmartDeviceErrorCode::Overcurrent),
+        };
+        let message = IotMessage::with_payload(47, CommandType::Event, &event);
+        assert_eq!(
+            message.get_message_data(),
+            r#"{"device_id":47,"at":1792132200,"kind":{"Malfunction":"Overcurrent"}}"#
+        );
+        assert_eq!(message.get_payload::<DeviceEvent>().unwrap(), event);
+        assert_eq!(event.to_string(), "#47: Overcurrent error.");
+    }
+
+    /// Простой генератор псевдослучайных чисел (xorshift) для fuzz-тестов
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// Разбор случайных посылок не должен приводить к панике
+    #[test]
+    fn test_decode_random_input_never_panics() {
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+
+        for _ in 0..100_000 {
+            let length = (rng.next() % 24) as usize;
+            let raw_bytes: Vec<u8> = (0..length).map(|_| rng.next() as u8).collect();
+            for version in [FrameVersion::V1, FrameVersion::V2, FrameVersion::V3] {
+                let _ = IotMessage::decode(&raw_bytes, version);
+                let _ = crate::receive_message(&mut raw_bytes.as_slice(), version);
+            }
+        }
+    }
+
+    /// Разбор искажённых корректных посылок не должен приводить к панике
+    #[test]
+    fn test_decode_mutated_frames_never_panics() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let message = IotMessage::new(47, CommandType::GetStatus, "status".to_string());
+
+        for i in 0..100_000 {
+            let version = match i % 3 {
+                0 => FrameVersion::V1,
+                1 => FrameVersion::V2,
+                _ => FrameVersion::V3,
+            };
+            let mut raw_bytes = message.encode(version);
+            match rng.next() % 3 {
+                0 => {
+                    let index = (rng.next() as usize) % raw_bytes.len();
+                    raw_bytes[index] = rng.next() as u8;
+                }
+                1 => raw_bytes.truncate((rng.next() as usize) % raw_bytes.len()),
+                _ => raw_bytes.push(rng.next() as u8),
+            }
+            let _ = IotMessage::decode(&raw_bytes, version);
+            let _ = crate::receive_message(&mut raw_bytes.as_slice(), version);
+        }
+    }
+
+    /// Все возможные посылки длиной до 3 байт отклоняются без паники
+    #[test]
+    fn test_decode_short_input_exhaustive() {
+        assert!(IotMessage::try_from([].as_slice()).is_err());
+        for a in 0..=u8::MAX {
+            assert!(IotMessage::try_from([a].as_slice()).is_err());
+            for b in 0..=u8::MAX {
+                assert!(IotMessage::try_from([a, b].as_slice()).is_err());
+                assert!(IotMessage::try_from([a, b, 0].as_slice()).is_err());
+            }
         }
     }
 }
@@ -1,12 +1,19 @@
-use crate::iot_error::{ConnectError, RequestError};
-use crate::iot_message::IotMessage;
+use crate::iot_error::{ConnectError, ReceptionError, RequestError, TransmissionError};
+use crate::iot_handshake::{self, Capabilities, HandshakeOffer, Negotiated};
+use crate::iot_message::{CommandType, DeviceEvent, IotMessage, ResponseStatus};
+use crate::iot_spec::FrameVersion;
 use std::io;
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Ограничение времени отправки посылки клиенту по умолчанию
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// IoT сервер.
 pub struct IotServer {
     tcp: TcpListener,
+    offer: HandshakeOffer,
 }
 
 impl IotServer {
@@ -16,26 +23,74 @@ impl IotServer {
         Addrs: ToSocketAddrs,
     {
         let tcp = TcpListener::bind(addrs)?;
-        Ok(Self { tcp })
+        Ok(Self {
+            tcp,
+            offer: HandshakeOffer::default(),
+        })
+    }
+
+    /// Задаём поддерживаемые сервером версии протокола и возможности.
+    pub fn with_offer(mut self, offer: HandshakeOffer) -> Self {
+        self.offer = offer;
+        self
+    }
+
+    /// Адрес, на котором сервер принимает соединения.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
     }
 
     /// Принимаем входящее соединение и производим handshake.
     pub fn accept(&self) -> Result<IotConnection, ConnectError> {
+        self.accept_pending()?.handshake()
+    }
+
+    /// Принимаем входящее соединение без handshake.
+    ///
+    /// Позволяет провести handshake в другом потоке, чтобы медленный клиент
+    /// не задерживал приём остальных соединений.
+    pub fn accept_pending(&self) -> io::Result<PendingConnection> {
         let (stream, _) = self.tcp.accept()?;
-        Self::try_handshake(stream)
+        Ok(PendingConnection {
+            stream,
+            offer: self.offer.clone(),
+        })
     }
+}
 
-    /// Проводим handshake, чтобы убедиться, что клиент поддерживает IoT protocol:
-    /// 1) ожидаем байты "iot_clnt",
-    /// 1) отправляем байты "iot_serv" в ответ.
-    fn try_handshake(mut stream: TcpStream) -> Result<IotConnection, ConnectError> {
-        let mut buf = [0; 8];
-        stream.read_exact(&mut buf)?;
-        if &buf != b"iot_clnt" {
-            return Err(ConnectError::BadHandshake);
-        }
-        stream.write_all(b"iot_serv")?;
-        Ok(IotConnection { stream })
+/// Принятое соединение, для которого ещё не проведён handshake.
+pub struct PendingConnection {
+    stream: TcpStream,
+    offer: HandshakeOffer,
+}
+
+impl PendingConnection {
+    /// Проводим handshake, чтобы убедиться, что клиент поддерживает IoT protocol,
+    /// и согласовать версию протокола и возможности (см. `iot_handshake`).
+    /// Клиенты, использующие устаревший handshake, также поддерживаются.
+    ///
+    /// Время отправки посылок ограничено `DEFAULT_WRITE_TIMEOUT`, чтобы клиент,
+    /// не читающий ответы и события, не блокировал отправляющий поток.
+    pub fn handshake(mut self) -> Result<IotConnection, ConnectError> {
+        self.stream.set_write_timeout(Some(DEFAULT_WRITE_TIMEOUT))?;
+        let negotiated = iot_handshake::server_handshake(&mut self.stream, &self.offer)?;
+        let writer = Arc::new(Mutex::new(self.stream.try_clone()?));
+        Ok(IotConnection {
+            stream: self.stream,
+            writer,
+            negotiated,
+        })
+    }
+
+    /// Ограничение времени ожидания данных от клиента (`None` - без ограничения).
+    /// Действует и на handshake, и на последующий обмен запросами.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Address of connected client
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
     }
 }
 
@@ -43,23 +98,187 @@ impl IotServer {
 /// Позволяет обрабатывать запросы.
 pub struct IotConnection {
     stream: TcpStream,
+    /// Поток для отправки посылок, общий с `EventSender`
+    writer: Arc<Mutex<TcpStream>>,
+    negotiated: Negotiated,
 }
 
 impl IotConnection {
     /// Обрабатываем запрос и возвращаем ответ используя логику
     /// предоставленную вызывающей стороной.
+    ///
+    /// Ответу присваивается номер запроса, на который он отвечает.
+    /// Запрос с неверной CRC или некорректным форматом до обработчика не доходит:
+    /// клиенту отправляется ответ `CommandType::ProtocolError`, а вызывающей стороне - ошибка приема.
     pub fn process_request<F>(&mut self, message_handler: F) -> Result<(), RequestError>
     where
         F: FnOnce(IotMessage) -> IotMessage,
     {
-        let request = super::receive_message(&mut self.stream)?;
+        let Some(request) = self.next_request()? else {
+            return Err(ReceptionError::Io(io::ErrorKind::UnexpectedEof.into()).into());
+        };
+        let seq = request.get_seq();
         let response = message_handler(request);
-        super::send_message(response, &mut self.stream)?;
+        self.send_response(response.with_seq(seq))
+    }
+
+    /// Обрабатываем запросы, пока клиент не закроет соединение.
+    ///
+    /// Некорректные запросы отклоняются (см. `process_request`), и обработка продолжается.
+    /// Закрытие соединения клиентом между запросами - штатное завершение,
+    /// обрыв посреди посылки и прочие ошибки IO возвращаются вызывающей стороне.
+    pub fn serve<F>(&mut self, mut message_handler: F) -> Result<(), RequestError>
+    where
+        F: FnMut(IotMessage) -> IotMessage,
+    {
+        loop {
+            match self.next_request() {
+                Ok(Some(request)) => {
+                    let seq = request.get_seq();
+                    let response = message_handler(request);
+                    self.send_response(response.with_seq(seq))?;
+                }
+                Ok(None) => return Ok(()),
+                Err(RequestError::Recv(ReceptionError::BadCRC | ReceptionError::Decode(_))) => {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Получение очередного запроса.
+    ///
+    /// Возвращает `None`, если клиент закрыл соединение между запросами.
+    /// Некорректный запрос отклоняется ответом `CommandType::ProtocolError`.
+    /// Вместе с `send_response` позволяет отвечать на запросы в произвольном порядке,
+    /// если согласованная версия протокола передаёт номер запроса.
+    pub fn next_request(&mut self) -> Result<Option<IotMessage>, RequestError> {
+        match super::receive_message_or_eof(&mut self.stream, self.negotiated.version) {
+            Ok(request) => Ok(request),
+            Err(e @ (ReceptionError::BadCRC | ReceptionError::Decode(_))) => {
+                self.reject_request(&e.to_string())?;
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Отправка ответа на запрос.
+    ///
+    /// Номер запроса в ответе должен совпадать с номером запроса, на который он отвечает.
+    pub fn send_response(&mut self, response: IotMessage) -> Result<(), RequestError> {
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        super::send_message(response, self.negotiated.version, &mut *writer)?;
         Ok(())
     }
 
+    /// Отправитель событий клиенту из других потоков.
+    ///
+    /// `None`, если при handshake не согласована возможность `Capabilities::SUBSCRIPTIONS`.
+    pub fn event_sender(&self) -> Option<EventSender> {
+        self.negotiated
+            .capabilities
+            .contains(Capabilities::SUBSCRIPTIONS)
+            .then(|| EventSender {
+                writer: Arc::clone(&self.writer),
+                version: self.negotiated.version,
+            })
+    }
+
+    /// Отправка клиенту ответа об ошибке протокола
+    pub fn reject_request(&mut self, reason: &str) -> Result<(), RequestError> {
+        let response = IotMessage::new(0, CommandType::ProtocolError, reason.to_string())
+            .with_status(ResponseStatus::BadRequest);
+        self.send_response(response)
+    }
+
+    /// Согласованная версия протокола.
+    pub fn version(&self) -> FrameVersion {
+        self.negotiated.version
+    }
+
+    /// Возможности, поддерживаемые и клиентом, и сервером.
+    pub fn capabilities(&self) -> Capabilities {
+        self.negotiated.capabilities
+    }
+
+    /// Ограничение времени ожидания очередного запроса (`None` - без ограничения).
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Ограничение времени отправки посылки, в том числе события (`None` - без ограничения).
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+
     /// Address of connected client
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         self.stream.peer_addr()
     }
 }
+
+/// Отправка клиенту посылок `CommandType::Event` без запроса.
+///
+/// Посылки событий и ответы на запросы не перемешиваются: каждая посылка
+/// отправляется целиком.
+///
+/// Если событие отправить не удалось (например, истекло время отправки), посылка
+/// могла уйти частично, поэтому соединение закрывается.
+#[derive(Clone)]
+pub struct EventSender {
+    writer: Arc<Mutex<TcpStream>>,
+    version: FrameVersion,
+}
+
+impl EventSender {
+    /// Отправка события устройства посылкой с номером запроса `0`
+    pub fn send(&self, event: &DeviceEvent) -> Result<(), TransmissionError> {
+        let event = IotMessage::with_payload(event.device_id, CommandType::Event, event);
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        super::send_message(event, self.version, &mut *writer).inspect_err(|_| {
+            // Соединение могло быть уже закрыто клиентом
+            let _ = writer.shutdown(Shutdown::Both);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_client::IotClient;
+    use std::thread;
+
+    #[test]
+    fn test_serve_multiple_requests_until_disconnect() {
+        let server = IotServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut connection = server.accept().unwrap();
+            let mut served = 0;
+            let result = connection.serve(|request| {
+                served += 1;
+                IotMessage::new(
+                    request.get_id(),
+                    request.get_command_type(),
+                    format!("response {served}"),
+                )
+            });
+            (result, served)
+        });
+
+        let mut client = IotClient::connect(addr).unwrap();
+        for i in 1..=3 {
+            let request = IotMessage::new(47, CommandType::GetStatus, String::new());
+            let response = client.send_request(request).unwrap();
+            assert_eq!(response.get_message_data(), format!("response {i}"));
+        }
+        drop(client);
+
+        let (result, served) = handle.join().unwrap();
+        assert!(result.is_ok());
+        assert_eq!(served, 3);
+    }
+}
@@ -0,0 +1,348 @@
+//! Спецификация формата посылки IoT protocol.
+//!
+//! Версия формата согласуется при handshake (см. `iot_handshake`),
+//! запросы и ответы внутри соединения передаются одинаковыми посылками.
+//!
+//! # Версия формата 1
+//!
+//! | Смещение  | Размер | Поле                                                  |
+//! |-----------|--------|-------------------------------------------------------|
+//! | 0         | 1      | Идентификатор устройства                              |
+//! | 1         | 1      | Код команды (см. `CommandType`)                       |
+//! | 2         | 2      | Длина данных `N`, big-endian                          |
+//! | 4         | `N`    | Данные, строка UTF-8                                  |
+//! | 4 + `N`   | 2      | CRC16/ARC по байтам `0..4 + N`, big-endian            |
+//!
+//! Ответы передаются в порядке поступления запросов.
+//!
+//! # Версия формата 2
+//!
+//! | Смещение  | Размер | Поле                                                  |
+//! |-----------|--------|-------------------------------------------------------|
+//! | 0         | 1      | Идентификатор устройства                              |
+//! | 1         | 1      | Код команды (см. `CommandType`)                       |
+//! | 2         | 2      | Номер запроса, big-endian                             |
+//! | 4         | 2      | Длина данных `N`, big-endian                          |
+//! | 6         | `N`    | Данные, строка UTF-8                                  |
+//! | 6 + `N`   | 2      | CRC16/ARC по байтам `0..6 + N`, big-endian            |
+//!
+//! Ответ несёт номер запроса, на который он отвечает, поэтому клиент может отправить
+//! несколько запросов подряд, а сервер - ответить на них в любом порядке.
+//! Номер `0` зарезервирован для ответов, которые нельзя сопоставить с запросом
+//! (например, отказ в приёме искажённой посылки).
+//!
+//! # Версия формата 3
+//!
+//! | Смещение  | Размер | Поле                                                  |
+//! |-----------|--------|-------------------------------------------------------|
+//! | 0         | 1      | Идентификатор устройства                              |
+//! | 1         | 1      | Код команды (см. `CommandType`)                       |
+//! | 2         | 2      | Номер запроса, big-endian                             |
+//! | 4         | 1      | Код результата (см. `ResponseStatus`)                 |
+//! | 5         | 2      | Длина данных `N`, big-endian                          |
+//! | 7         | `N`    | Данные, строка UTF-8                                  |
+//! | 7 + `N`   | 2      | CRC16/ARC по байтам `0..7 + N`, big-endian            |
+//!
+//! Коды результата:
+//!
+//! | Код    | Результат                                             |
+//! |--------|-------------------------------------------------------|
+//! | `0x00` | Успешно (в запросах всегда `0x00`)                    |
+//! | `0x01` | Некорректный запрос                                   |
+//! | `0x02` | Обращение к несуществующему устройству                |
+//! | `0x03` | Команда не поддерживается                             |
+//! | `0x04` | Недостаточно прав                                     |
+//! | `0x10` | Неисправность устройства: перегрузка по току          |
+//! | `0x11` | Неисправность устройства: перегрузка по напряжению    |
+//! | `0x12` | Неисправность устройства: перегрев                    |
+//! | `0x13` | Неисправность устройства: слишком низкая температура  |
+//!
+//! В версиях 1 и 2 код результата не передаётся, поэтому сервер дублирует
+//! описание ошибки в данных ответа.
+//!
+//! # Команды
+//!
+//! | Код    | Команда          | Данные запроса          | Данные ответа       |
+//! |--------|------------------|-------------------------|---------------------|
+//! | `0x01` | `SetPowerOn`     | -                       | `StatusReport`      |
+//! | `0x02` | `SetPowerOff`    | -                       | `StatusReport`      |
+//! | `0x03` | `GetStatus`      | -                       | `StatusReport`      |
+//! | `0x04` | `GetPower`       | -                       | `PowerReport`       |
+//! | `0x05` | `Rename`         | новое имя (строка)      | `null`              |
+//! | `0x06` | `GetEnergy`      | -                       | `EnergyReport`      |
+//! | `0x07` | `ResetEnergy`    | -                       | `EnergyReport`      |
+//! | `0x08` | `GetTelemetry`   | -                       | `TelemetryReport`   |
+//! | `0x09` | `SetLimits`      | `ElectricalLimits`      | `TelemetryReport`   |
+//! | `0x0A` | `ResetFault`     | -                       | `StatusReport`      |
+//! | `0x0B` | `GetTripHistory` | -                       | `[TripRecord]`      |
+//! | `0x0C` | `InjectFault`    | `FaultInjectionRequest` | `StatusReport`      |
+//! | `0x10` | `ListDevices`    | -                       | `[DeviceInfo]`      |
+//! | `0x11` | `AddDevice`      | `NewDevice`             | `null`              |
+//! | `0x12` | `RemoveDevice`   | -                       | `null`              |
+//! | `0x20` | `GetTemperature` | -                       | `TemperatureReport` |
+//! | `0x21` | `SetThresholds`  | `TemperatureThresholds` | `TemperatureReport` |
+//! | `0x30` | `AddTimer`       | `NewTimer`              | `ScheduleInfo`      |
+//! | `0x31` | `AddSchedule`    | `WeeklySchedule`        | `ScheduleInfo`      |
+//! | `0x32` | `ListSchedules`  | -                       | `[ScheduleInfo]`    |
+//! | `0x33` | `DeleteSchedule` | идентификатор (число)   | `null`              |
+//! | `0x40` | `SaveScene`      | `Scene`                 | `null`              |
+//! | `0x41` | `ListScenes`     | -                       | `[Scene]`           |
+//! | `0x42` | `ApplyScene`     | имя сцены (строка)      | `SceneReport`       |
+//! | `0x50` | `SaveGroup`      | `DeviceGroup`           | `null`              |
+//! | `0x51` | `ListGroups`     | -                       | `[DeviceGroup]`     |
+//! | `0x52` | `DeleteGroup`    | имя группы (строка)     | `null`              |
+//! | `0x53` | `GroupCommand`   | `GroupRequest`          | `GroupReport`       |
+//! | `0x60` | `Subscribe`      | `Subscription`          | `null`              |
+//! | `0x61` | `Unsubscribe`    | -                       | `null`              |
+//! | `0x62` | `Event`          | -                       | `DeviceEvent`       |
+//! | `0xFF` | `ProtocolError`  | -                       | описание ошибки     |
+//!
+//! Команды адресуются устройству по идентификатору из заголовка посылки; `AddDevice` создаёт
+//! устройство с этим идентификатором, в запросе `ListDevices` идентификатор не используется.
+//! `RemoveDevice` удаляет также таймеры и расписания устройства и исключает его из сохранённых
+//! групп и сцен; группы и сцены, в которых не осталось устройств, удаляются. Устройство,
+//! снова добавленное с тем же идентификатором, в них не возвращается.
+//!
+//! Типизированные данные запросов и ответов передаются в поле данных в виде JSON.
+//! Если код результата отличен от `0x00`, данные содержат текстовое описание ошибки.
+//! Ответ, данные которого не помещаются в посылку, заменяется ошибкой с кодом `0x01`.
+//! Имена устройств, сцен и групп длиннее `MAX_NAME_LENGTH` байт отклоняются с тем же кодом.
+//!
+//! Таймеры, расписания и сцены хранятся на сервере; время недельных расписаний задаётся по UTC.
+//! В запросах `SaveScene`, `ListScenes` и `ApplyScene` идентификатор устройства не используется;
+//! сцена применяется атомарно: другие запросы не видят частично применённую сцену.
+//!
+//! Группы устройств (например, комнаты) также хранятся на сервере, идентификатор устройства
+//! в запросах групп не используется. По `GroupCommand` сервер выполняет вложенную команду
+//! для каждого устройства группы так же, как запрос, адресованный этому устройству, и
+//! возвращает код результата и данные ответа каждого устройства. Ошибка одного устройства
+//! не мешает выполнению команды остальными; команды групп не могут быть вложенными.
+//!
+//! `Subscribe` доступна, только если при handshake согласована возможность `SUBSCRIPTIONS`
+//! (иначе сервер отвечает кодом результата `0x03`). После подписки сервер, не дожидаясь
+//! запросов, отправляет по тому же соединению посылки `Event` с номером запроса `0`:
+//! при изменении состояния питания устройства, его неисправности и пересечении порога
+//! мощности подписки. Клиент отличает события от ответов по коду команды. Повторный
+//! `Subscribe` заменяет подписку, `Unsubscribe` и закрытие соединения отменяют её.
+//! Асинхронные клиент и сервер этой реализации возможность `SUBSCRIPTIONS` не предлагают.
+//!
+//! `InjectFault` выполняется, только если токен в запросе совпадает с токеном администратора,
+//! заданным серверу; иначе сервер отвечает кодом результата `0x04`.
+//!
+//! Посылка с кодом команды, не известным получателю, принимается: сервер отвечает на неё
+//! кодом результата `0x03`, поэтому новые команды не нарушают обмен со старыми узлами.
+//!
+//! # Общие правила
+//! - `N` всегда равно количеству байт данных, фактически переданных в посылке,
+//!   данные передаются как есть, без обрезки пробелов и выравнивания;
+//! - при `N = 0` за заголовком сразу следует CRC;
+//! - CRC16/ARC: полином `0x8005` (отражённый `0xA001`), начальное значение `0x0000`,
+//!   без финального XOR; контрольное значение для ASCII "123456789" - `0xBB3D`;
+//! - посылка с неверной CRC, неизвестным кодом результата или данными не в UTF-8
+//!   отклоняется получателем.
+
+use crc16::{State, ARC};
+
+/// Версия формата посылки
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameVersion {
+    /// Без номера запроса
+    V1 = 1,
+    /// С номером запроса
+    V2 = 2,
+    /// С номером запроса и кодом результата
+    V3 = 3,
+}
+
+impl FrameVersion {
+    /// Последняя версия формата, поддерживаемая этой реализацией
+    pub const LATEST: Self = Self::V3;
+
+    /// Длина заголовка посылки
+    pub const fn header_length(self) -> usize {
+        match self {
+            Self::V1 => 4,
+            Self::V2 => 6,
+            Self::V3 => 7,
+        }
+    }
+
+    /// Длина данных, указанная в заголовке посылки
+    ///
+    /// `header` должен содержать не меньше `header_length()` байт.
+    pub fn data_length(self, header: &[u8]) -> usize {
+        let offset = self.header_length() - 2;
+        usize::from(u16::from_be_bytes([header[offset], header[offset + 1]]))
+    }
+
+    /// Полная длина посылки с заданным заголовком
+    pub fn frame_length(self, header: &[u8]) -> usize {
+        self.header_length() + self.data_length(header) + CRC_LENGTH
+    }
+}
+
+impl TryFrom<u8> for FrameVersion {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            other => Err(other),
+        }
+    }
+}
+
+/// Максимальная длина заголовка среди всех версий формата
+pub const MAX_HEADER_LENGTH: usize = FrameVersion::LATEST.header_length();
+
+/// Длина CRC
+pub const CRC_LENGTH: usize = 2;
+
+/// Максимальная длина данных в посылке
+pub const MAX_DATA_LENGTH: usize = u16::MAX as usize;
+
+/// Максимальная длина имени устройства, сцены или группы, байт
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// Расчёт CRC16/ARC по байтам посылки без поля CRC
+pub fn crc(bytes: &[u8]) -> u16 {
+    let mut state = State::<ARC>::new();
+    state.update(bytes);
+    state.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iot_message::{CommandType, IotMessage, ResponseStatus};
+    use smart_socket::SmartDeviceErrorCode;
+
+    /// Эталонные посылки, рассчитанные независимо от реализации
+    fn golden_vectors() -> Vec<(FrameVersion, IotMessage, Vec<u8>)> {
+        vec![
+            (
+                FrameVersion::V1,
+                IotMessage::new(1, CommandType::SetPowerOn, "test".to_string()),
+                vec![1, 1, 0, 4, 116, 101, 115, 116, 52, 14],
+            ),
+            (
+                FrameVersion::V1,
+                IotMessage::new(47, CommandType::GetStatus, String::new()),
+                vec![47, 3, 0, 0, 212, 248],
+            ),
+            (
+                FrameVersion::V1,
+                IotMessage::new(47, CommandType::SetPowerOff, " x ".to_string()),
+                vec![47, 2, 0, 3, 32, 120, 32, 118, 253],
+            ),
+            (
+                FrameVersion::V1,
+                IotMessage::new(5, CommandType::ProtocolError, "Вкл".to_string()),
+                vec![5, 255, 0, 6, 208, 146, 208, 186, 208, 187, 63, 157],
+            ),
+            (
+                FrameVersion::V2,
+                IotMessage::new(1, CommandType::SetPowerOn, "test".to_string()).with_seq(1),
+                vec![1, 1, 0, 1, 0, 4, 116, 101, 115, 116, 173, 147],
+            ),
+            (
+                FrameVersion::V2,
+                IotMessage::new(47, CommandType::GetStatus, String::new()).with_seq(0x1234),
+                vec![47, 3, 18, 52, 0, 0, 41, 7],
+            ),
+            (
+                FrameVersion::V2,
+                IotMessage::new(47, CommandType::SetPowerOff, " x ".to_string()).with_seq(0xFFFF),
+                vec![47, 2, 255, 255, 0, 3, 32, 120, 32, 190, 96],
+            ),
+            (
+                FrameVersion::V3,
+                IotMessage::new(1, CommandType::SetPowerOn, "test".to_string()).with_seq(1),
+                vec![1, 1, 0, 1, 0, 0, 4, 116, 101, 115, 116, 205, 75],
+            ),
+            (
+                FrameVersion::V3,
+                IotMessage::new(47, CommandType::SetPowerOn, String::new())
+                    .with_seq(5)
+                    .with_status(ResponseStatus::DeviceFault(
+                        SmartDeviceErrorCode::Overcurrent,
+                    )),
+                vec![47, 1, 0, 5, 16, 0, 0, 26, 222],
+            ),
+            (
+                FrameVersion::V3,
+                IotMessage::new(9, CommandType::GetStatus, String::new())
+                    .with_seq(2)
+                    .with_status(ResponseStatus::UnknownDevice),
+                vec![9, 3, 0, 2, 2, 0, 0, 75, 57],
+            ),
+        ]
+    }
+
+    /// Контрольное значение CRC16/ARC
+    #[test]
+    fn test_crc_check_value() {
+        assert_eq!(crc(b"123456789"), 0xBB3D);
+    }
+
+    #[test]
+    fn test_golden_vectors_encode() {
+        for (version, message, raw_bytes) in golden_vectors() {
+            assert_eq!(message.encode(version), raw_bytes);
+        }
+    }
+
+    #[test]
+    fn test_golden_vectors_decode() {
+        for (version, message, raw_bytes) in golden_vectors() {
+            assert_eq!(version.frame_length(&raw_bytes), raw_bytes.len());
+            assert_eq!(IotMessage::decode(&raw_bytes, version), Ok(message));
+        }
+    }
+
+    /// Посылки с пустыми данными и данными из пробелов проходят через поток без искажений
+    #[test]
+    fn test_golden_vectors_loopback() {
+        for (version, message, raw_bytes) in golden_vectors() {
+            let mut buffer: Vec<u8> = Vec::new();
+            crate::send_message(message.clone(), version, &mut buffer).unwrap();
+            assert_eq!(buffer, raw_bytes);
+
+            let mut reader = buffer.as_slice();
+            assert_eq!(
+                crate::receive_message(&mut reader, version).unwrap(),
+                message
+            );
+            assert!(reader.is_empty());
+        }
+    }
+
+    /// В версии 1 номер запроса не передаётся
+    #[test]
+    fn test_v1_drops_seq() {
+        let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
+        let raw_bytes = message.clone().with_seq(7).encode(FrameVersion::V1);
+
+        assert_eq!(
+            IotMessage::decode(&raw_bytes, FrameVersion::V1),
+            Ok(message)
+        );
+    }
+
+    /// В версии 2 код результата не передаётся
+    #[test]
+    fn test_v2_drops_status() {
+        let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string()).with_seq(3);
+        let raw_bytes = message
+            .clone()
+            .with_status(ResponseStatus::Unauthorized)
+            .encode(FrameVersion::V2);
+
+        assert_eq!(
+            IotMessage::decode(&raw_bytes, FrameVersion::V2),
+            Ok(message)
+        );
+    }
+}
@@ -1,23 +1,35 @@
-use iot_error::ReceptionError;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 
 use iot_message::IotMessage;
+use iot_spec::{FrameVersion, MAX_HEADER_LENGTH};
 
 pub mod iot_client;
+pub mod iot_command;
+
+#[cfg(feature = "async")]
+pub mod iot_async_client;
+#[cfg(feature = "async")]
+pub mod iot_async_server;
+
+#[cfg(feature = "codec")]
+pub mod iot_codec;
 
 pub mod iot_error;
+pub mod iot_handshake;
 pub mod iot_message;
 pub mod iot_server;
+pub mod iot_spec;
 
 /// Отправка сообщения
 /// # Формат
-/// Запрос: ID + команда + CRC
-/// Отклик: ID + команда + длина данных + данные + CRC
+/// Запрос и отклик: ID + команда + [номер запроса] + длина данных + данные + CRC,
+/// подробнее в модуле `iot_spec`
 fn send_message<Writer: Write>(
     message: IotMessage,
+    version: FrameVersion,
     writer: &mut Writer,
 ) -> Result<(), iot_error::TransmissionError> {
-    let raw_bytes = message.serialize_to_raw_byte_data();
+    let raw_bytes = message.encode(version);
 
     writer.write_all(raw_bytes.as_slice())?;
 
@@ -26,51 +38,212 @@ fn send_message<Writer: Write>(
 
 /// Прием сообщения
 ///
+/// Читает заголовок, затем данные и CRC согласно заявленной длине.
 fn receive_message<Reader: Read>(
     reader: &mut Reader,
+    version: FrameVersion,
 ) -> Result<IotMessage, iot_error::ReceptionError> {
-    let mut raw_bytes = [0; 4];
-    let mut raw_message: Vec<u8> = Vec::new();
-
-    reader.read_exact(&mut raw_bytes)?;
-    raw_message.append(&mut raw_bytes.as_slice().to_vec());
-
-    let data_length = u16::from_be_bytes([raw_bytes[2], raw_bytes[3]]);
-
-    if data_length > 0 {
-        let mut message_data = vec![0; data_length as usize]; // data field
-        let mut crc = [0; 2]; // CRC
-        reader.read_exact(&mut message_data)?;
-        raw_message.append(&mut message_data);
-
-        reader.read_exact(&mut crc)?;
-        raw_message.append(&mut crc.to_vec());
-    } else {
-        let mut raw_bytes = [0; 3]; // CRC + zero byte
-        reader.read_exact(&mut raw_bytes)?;
-        raw_message.append(&mut raw_bytes.to_vec());
+    match receive_message_or_eof(reader, version)? {
+        Some(message) => Ok(message),
+        None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
     }
+}
 
-    if let Some(message) = IotMessage::deserialize_from_raw_byte_data(raw_message) {
-        Ok(message)
-    } else {
-        Err(ReceptionError::BadFormat)
+/// Прием сообщения с учётом закрытия соединения
+///
+/// Возвращает `None`, если поток закончился до начала очередной посылки.
+/// Обрыв потока посреди посылки считается ошибкой IO, а истечение времени ожидания
+/// посреди посылки - ошибкой `ReceptionError::PartialFrame`. Истечение времени ожидания
+/// до начала посылки (`ReceptionError::Io`) поток не нарушает.
+fn receive_message_or_eof<Reader: Read>(
+    reader: &mut Reader,
+    version: FrameVersion,
+) -> Result<Option<IotMessage>, iot_error::ReceptionError> {
+    let header_length = version.header_length();
+    let mut header = [0; MAX_HEADER_LENGTH];
+    loop {
+        match reader.read(&mut header[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
     }
+    reader
+        .read_exact(&mut header[1..header_length])
+        .map_err(partial_frame)?;
+
+    let mut raw_message = vec![0; version.frame_length(&header)];
+    raw_message[..header_length].copy_from_slice(&header[..header_length]);
+    reader
+        .read_exact(&mut raw_message[header_length..])
+        .map_err(partial_frame)?;
+
+    Ok(Some(IotMessage::decode(&raw_message, version)?))
+}
+
+/// Ошибка чтения продолжения посылки
+fn partial_frame(e: io::Error) -> iot_error::ReceptionError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            iot_error::ReceptionError::PartialFrame(e)
+        }
+        _ => e.into(),
+    }
+}
+
+/// Асинхронная отправка сообщения, формат совпадает с `send_message`
+#[cfg(feature = "async")]
+async fn send_message_async<Writer>(
+    message: IotMessage,
+    version: FrameVersion,
+    writer: &mut Writer,
+) -> Result<(), iot_error::TransmissionError>
+where
+    Writer: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let raw_bytes = message.encode(version);
+
+    writer.write_all(raw_bytes.as_slice()).await?;
+
+    Ok(())
+}
+
+/// Асинхронный прием сообщения с учётом закрытия соединения, см. `receive_message_or_eof`
+#[cfg(feature = "async")]
+async fn receive_message_or_eof_async<Reader>(
+    reader: &mut Reader,
+    version: FrameVersion,
+) -> Result<Option<IotMessage>, iot_error::ReceptionError>
+where
+    Reader: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let header_length = version.header_length();
+    let mut header = [0; MAX_HEADER_LENGTH];
+    if reader.read(&mut header[..1]).await? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut header[1..header_length]).await?;
+
+    let mut raw_message = vec![0; version.frame_length(&header)];
+    raw_message[..header_length].copy_from_slice(&header[..header_length]);
+    reader.read_exact(&mut raw_message[header_length..]).await?;
+
+    Ok(Some(IotMessage::decode(&raw_message, version)?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use iot_error::ReceptionError;
     use iot_message::CommandType;
     #[test]
     fn test_loopback_mode() {
         let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
         let mut buffer: Vec<u8> = Vec::new();
 
-        send_message(message.clone(), &mut buffer).unwrap();
+        send_message(message.clone(), FrameVersion::V1, &mut buffer).unwrap();
 
-        let received_message = receive_message(&mut buffer.as_slice()).unwrap();
+        let received_message = receive_message(&mut buffer.as_slice(), FrameVersion::V1).unwrap();
 
         assert_eq!(received_message, message);
     }
+
+    #[test]
+    fn test_bad_crc_rejected() {
+        let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
+        let mut buffer: Vec<u8> = Vec::new();
+
+        send_message(message, FrameVersion::V1, &mut buffer).unwrap();
+        buffer[5] ^= 0x01;
+
+        let result = receive_message(&mut buffer.as_slice(), FrameVersion::V1);
+
+        assert!(matches!(result, Err(ReceptionError::BadCRC)));
+    }
+
+    #[test]
+    fn test_bad_crc_rejected_before_parsing() {
+        let message = IotMessage::new(1, CommandType::GetStatus, "test".to_string());
+        let mut buffer: Vec<u8> = Vec::new();
+        send_message(message, FrameVersion::V3, &mut buffer).unwrap();
+
+        // Неизвестный код результата и данные, не являющиеся UTF-8
+        for (index, byte) in [(4, 0x0F), (7, 0xFF)] {
+            let mut corrupted = buffer.clone();
+            corrupted[index] = byte;
+            let result = receive_message(&mut corrupted.as_slice(), FrameVersion::V3);
+            assert!(matches!(result, Err(ReceptionError::BadCRC)), "{index}");
+        }
+    }
+
+    #[test]
+    fn test_eof_between_messages() {
+        let message = IotMessage::new(1, CommandType::GetStatus, String::new());
+        let mut buffer: Vec<u8> = Vec::new();
+
+        send_message(message.clone(), FrameVersion::V1, &mut buffer).unwrap();
+        send_message(message.clone(), FrameVersion::V1, &mut buffer).unwrap();
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(
+            receive_message_or_eof(&mut reader, FrameVersion::V1).unwrap(),
+            Some(message.clone())
+        );
+        assert_eq!(
+            receive_message_or_eof(&mut reader, FrameVersion::V1).unwrap(),
+            Some(message)
+        );
+        assert_eq!(
+            receive_message_or_eof(&mut reader, FrameVersion::V1).unwrap(),
+            None
+        );
+    }
+
+    /// Поток, данные из которого не приходят до истечения времени ожидания
+    struct Stalled;
+
+    impl Read for Stalled {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    #[test]
+    fn test_timeout_inside_message() {
+        let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
+        let mut buffer: Vec<u8> = Vec::new();
+        send_message(message, FrameVersion::V1, &mut buffer).unwrap();
+
+        let result = receive_message_or_eof(&mut Stalled, FrameVersion::V1);
+        assert!(matches!(result, Err(ReceptionError::Io(_))));
+
+        for cut in [2, buffer.len() - 1] {
+            let mut reader = buffer[..cut].chain(Stalled);
+            let result = receive_message_or_eof(&mut reader, FrameVersion::V1);
+            assert!(
+                matches!(result, Err(ReceptionError::PartialFrame(_))),
+                "{cut}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_eof_inside_message() {
+        let message = IotMessage::new(1, CommandType::SetPowerOn, "test".to_string());
+        let mut buffer: Vec<u8> = Vec::new();
+
+        send_message(message, FrameVersion::V1, &mut buffer).unwrap();
+        buffer.truncate(buffer.len() - 1);
+
+        let result = receive_message_or_eof(&mut buffer.as_slice(), FrameVersion::V1);
+
+        assert!(
+            matches!(result, Err(ReceptionError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+    }
 }
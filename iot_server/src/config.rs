@@ -0,0 +1,352 @@
+use smart_socket::electrical::LineSupply;
+use smart_socket::faults::{FaultInjection, RandomFaults};
+use smart_socket::load::{ConstantLoad, LoadModel, NoisyLoad, ProfileLoad};
+use smart_socket::SmartDeviceErrorCode;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Адрес сервера по умолчанию
+const DEFAULT_ADDR: &str = "127.0.0.1:55331";
+
+/// Максимальное число одновременно обслуживаемых клиентов по умолчанию
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Время ожидания запроса от клиента по умолчанию, секунды
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+
+/// Шаг имитации работы устройств по умолчанию, миллисекунды
+const DEFAULT_SIM_INTERVAL_MS: u64 = 1000;
+
+/// Параметры запуска сервера
+pub struct ServerConfig {
+    /// Адрес, на котором сервер принимает соединения
+    pub addr: String,
+
+    /// Максимальное число одновременно обслуживаемых клиентов,
+    /// соединения сверх лимита закрываются сразу после приёма
+    pub max_connections: usize,
+
+    /// Время ожидания запроса, после которого молчащий клиент отключается
+    pub idle_timeout: Duration,
+
+    /// Шаг имитации работы устройств (потребления нагрузки и т.п.)
+    pub sim_interval: Duration,
+
+    /// Модель нагрузки, подключённой к розетке по умолчанию
+    pub load: Option<Box<dyn LoadModel>>,
+
+    /// Модель сети, к которой подключена розетка по умолчанию
+    pub supply: Option<LineSupply>,
+
+    /// Выдержка перед автоматическим повторным включением розетки по умолчанию
+    /// после защитного отключения (`None` - только сброс командой `ResetFault`)
+    pub auto_reclose: Option<Duration>,
+
+    /// Токен администратора для команды `InjectFault` (`None` - команда отключена)
+    pub admin_token: Option<String>,
+
+    /// Неисправности, вносимые в розетку по умолчанию при запуске
+    pub faults: Vec<FaultInjection>,
+
+    /// Файл правил автоматизации, правила проверяются при запуске
+    pub rules: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: DEFAULT_ADDR.to_string(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+            sim_interval: Duration::from_millis(DEFAULT_SIM_INTERVAL_MS),
+            load: None,
+            supply: None,
+            auto_reclose: None,
+            admin_token: None,
+            faults: Vec::new(),
+            rules: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Разбор аргументов командной строки:
+    /// `[--addr <ip:port>] [--max-connections <n>] [--idle-timeout <секунды>]
+    /// [--sim-interval <мс>] [--load <модель>] [--voltage <В>[:<±В>]]
+    /// [--auto-reclose <секунды>] [--admin-token <токен>] [--fault <неисправность>]...
+    /// [--random-faults <вероятность>:<секунды>] [--rules <файл>]`, модели нагрузки описаны
+    /// в `parse_load`, неисправности - в `parse_fault`, формат правил - в `rules::Rule`
+    pub fn from_args<Args: Iterator<Item = String>>(mut args: Args) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for '{arg}'"));
+            match arg.as_str() {
+                "--addr" => config.addr = value()?,
+                "--max-connections" => {
+                    config.max_connections = parse_number(&arg, &value()?)?;
+                    if config.max_connections == 0 {
+                        return Err(String::from("'--max-connections' must be positive"));
+                    }
+                }
+                "--idle-timeout" => {
+                    config.idle_timeout = Duration::from_secs(parse_number(&arg, &value()?)?);
+                    if config.idle_timeout.is_zero() {
+                        return Err(String::from("'--idle-timeout' must be positive"));
+                    }
+                }
+                "--sim-interval" => {
+                    config.sim_interval = Duration::from_millis(parse_number(&arg, &value()?)?);
+                    if config.sim_interval.is_zero() {
+                        return Err(String::from("'--sim-interval' must be positive"));
+                    }
+                }
+                "--load" => config.load = Some(parse_load(&value()?)?),
+                "--voltage" => config.supply = Some(parse_supply(&value()?)?),
+                "--rules" => config.rules = Some(PathBuf::from(value()?)),
+                "--admin-token" => config.admin_token = Some(value()?),
+                "--fault" => config.faults.push(parse_fault(&value()?)?),
+                "--random-faults" => config.faults.push(parse_random_faults(&value()?)?),
+                "--auto-reclose" => {
+                    config.auto_reclose = Some(Duration::from_secs(parse_number(&arg, &value()?)?))
+                }
+                _ => return Err(format!("Unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{value}' for '{arg}'"))
+}
+
+/// Разбор модели сети: `<В>` - постоянное напряжение, `<В>:<±В>` - с колебаниями
+fn parse_supply(spec: &str) -> Result<LineSupply, String> {
+    let (nominal, amplitude) = spec.split_once(':').unwrap_or((spec, "0"));
+    Ok(LineSupply::new(
+        parse_quantity("--voltage", nominal)?,
+        parse_quantity("--voltage", amplitude)?,
+    ))
+}
+
+/// Разбор запланированной неисправности: `<код>:<задержка, с>[:<длительность, с>]`,
+/// коды - `overcurrent`, `overvoltage`, `overheat`, `underheat`; без длительности
+/// неисправность действует до отмены
+fn parse_fault(spec: &str) -> Result<FaultInjection, String> {
+    let mut parts = spec.split(':');
+    let fault = match parts.next() {
+        Some("overcurrent") => SmartDeviceErrorCode::Overcurrent,
+        Some("overvoltage") => SmartDeviceErrorCode::Overvoltage,
+        Some("overheat") => SmartDeviceErrorCode::Overheat,
+        Some("underheat") => SmartDeviceErrorCode::Underheat,
+        _ => return Err(format!("Invalid fault '{spec}'")),
+    };
+    let delay = parts.next().ok_or(format!("Invalid fault '{spec}'"))?;
+    let duration = parts.next();
+    if parts.next().is_some() {
+        return Err(format!("Invalid fault '{spec}'"));
+    }
+    Ok(FaultInjection::Schedule {
+        delay: parse_seconds("--fault", delay)?,
+        fault,
+        duration: duration
+            .map(|duration| parse_seconds("--fault", duration))
+            .transpose()?,
+    })
+}
+
+/// Разбор случайных неисправностей: `<вероятность за секунду>:<длительность, с>`
+fn parse_random_faults(spec: &str) -> Result<FaultInjection, String> {
+    let (probability, duration) = spec
+        .split_once(':')
+        .ok_or(format!("Invalid random faults '{spec}'"))?;
+    let probability: f64 = parse_number("--random-faults", probability)?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(String::from(
+            "'--random-faults' probability must be in 0..=1",
+        ));
+    }
+    Ok(FaultInjection::Random(Some(RandomFaults {
+        probability,
+        duration: parse_seconds("--random-faults", duration)?,
+        faults: Vec::new(),
+    })))
+}
+
+/// Разбор физической величины (мощности, напряжения): конечное неотрицательное число
+fn parse_quantity(arg: &str, value: &str) -> Result<f32, String> {
+    let quantity: f32 = parse_number(arg, value)?;
+    if !quantity.is_finite() || quantity < 0.0 {
+        return Err(format!("Invalid value '{value}' for '{arg}'"));
+    }
+    Ok(quantity)
+}
+
+fn parse_seconds(arg: &str, value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_number(arg, value)?)
+        .map_err(|_| format!("Invalid value '{value}' for '{arg}'"))
+}
+
+/// Разбор модели нагрузки: `constant:<Вт>`, `noisy:<Вт>:<±Вт>`, `csv:<путь>`, `kettle`, `fridge`
+fn parse_load(spec: &str) -> Result<Box<dyn LoadModel>, String> {
+    let (kind, params) = spec.split_once(':').unwrap_or((spec, ""));
+    let load: Box<dyn LoadModel> = match (kind, params) {
+        ("constant", power) => Box::new(ConstantLoad::new(parse_quantity("--load", power)?)),
+        ("noisy", params) => {
+            let (base, amplitude) = params
+                .split_once(':')
+                .ok_or(format!("Invalid load model '{spec}'"))?;
+            Box::new(NoisyLoad::new(
+                parse_quantity("--load", base)?,
+                parse_quantity("--load", amplitude)?,
+            ))
+        }
+        ("csv", path) => Box::new(
+            ProfileLoad::from_csv_file(path).map_err(|e| format!("Invalid load profile: {e}"))?,
+        ),
+        ("kettle", "") => Box::new(ProfileLoad::kettle()),
+        ("fridge", "") => Box::new(ProfileLoad::fridge()),
+        _ => return Err(format!("Invalid load model '{spec}'")),
+    };
+    Ok(load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<ServerConfig, String> {
+        ServerConfig::from_args(args.split_whitespace().map(String::from))
+    }
+
+    fn error(args: &str) -> String {
+        parse(args).err().expect("arguments must be rejected")
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse("").unwrap();
+        assert_eq!(config.addr, DEFAULT_ADDR);
+        assert_eq!(config.max_connections, DEFAULT_MAX_CONNECTIONS);
+        assert_eq!(
+            config.sim_interval,
+            Duration::from_millis(DEFAULT_SIM_INTERVAL_MS)
+        );
+        assert!(config.load.is_none() && config.supply.is_none());
+        assert!(config.auto_reclose.is_none() && config.admin_token.is_none());
+        assert!(config.faults.is_empty() && config.rules.is_none());
+    }
+
+    #[test]
+    fn test_accepted_arguments() {
+        let config = parse(
+            "--addr 0.0.0.0:1 --max-connections 2 --idle-timeout 3 --sim-interval 4 \
+             --load constant:100 --voltage 120:5 --auto-reclose 6 --admin-token secret \
+             --fault overheat:1:2.5 --fault underheat:0 --random-faults 0.5:1 --rules rules.json",
+        )
+        .unwrap();
+        assert_eq!(config.addr, "0.0.0.0:1");
+        assert_eq!(config.max_connections, 2);
+        assert_eq!(config.idle_timeout, Duration::from_secs(3));
+        assert_eq!(config.sim_interval, Duration::from_millis(4));
+        assert_eq!(config.load.unwrap().power(Duration::ZERO), 100.0);
+        assert!((115.0..=125.0).contains(&config.supply.unwrap().voltage()));
+        assert_eq!(config.auto_reclose, Some(Duration::from_secs(6)));
+        assert_eq!(config.admin_token.as_deref(), Some("secret"));
+        assert_eq!(config.rules, Some(PathBuf::from("rules.json")));
+        assert_eq!(
+            config.faults,
+            [
+                FaultInjection::Schedule {
+                    delay: Duration::from_secs(1),
+                    fault: SmartDeviceErrorCode::Overheat,
+                    duration: Some(Duration::from_millis(2500)),
+                },
+                FaultInjection::Schedule {
+                    delay: Duration::ZERO,
+                    fault: SmartDeviceErrorCode::Underheat,
+                    duration: None,
+                },
+                FaultInjection::Random(Some(RandomFaults {
+                    probability: 0.5,
+                    duration: Duration::from_secs(1),
+                    faults: Vec::new(),
+                })),
+            ]
+        );
+
+        for load in ["noisy:100:10", "kettle", "fridge"] {
+            assert!(parse(&format!("--load {load}")).unwrap().load.is_some());
+        }
+        assert!(parse("--random-faults 0:1").is_ok());
+        assert!(parse("--random-faults 1:0").is_ok());
+    }
+
+    #[test]
+    fn test_rejected_arguments() {
+        assert_eq!(error("--verbose"), "Unknown argument '--verbose'");
+        assert_eq!(error("--addr"), "Missing value for '--addr'");
+        assert_eq!(
+            error("--max-connections 0"),
+            "'--max-connections' must be positive"
+        );
+        assert_eq!(
+            error("--max-connections -1"),
+            "Invalid value '-1' for '--max-connections'"
+        );
+        assert_eq!(
+            error("--sim-interval 0"),
+            "'--sim-interval' must be positive"
+        );
+        assert_eq!(
+            error("--idle-timeout 0"),
+            "'--idle-timeout' must be positive"
+        );
+        assert_eq!(
+            error("--idle-timeout soon"),
+            "Invalid value 'soon' for '--idle-timeout'"
+        );
+        assert_eq!(
+            error("--random-faults 1.5:1"),
+            "'--random-faults' probability must be in 0..=1"
+        );
+        assert_eq!(
+            error("--random-faults -0.1:1"),
+            "'--random-faults' probability must be in 0..=1"
+        );
+        assert_eq!(error("--random-faults 0.5"), "Invalid random faults '0.5'");
+        assert_eq!(
+            error("--random-faults 0.5:-1"),
+            "Invalid value '-1' for '--random-faults'"
+        );
+
+        for spec in ["sparks:1", "overheat", "overheat:1:2:3", "overheat:x"] {
+            assert!(parse(&format!("--fault {spec}")).is_err(), "{spec}");
+        }
+        for spec in [
+            "constant",
+            "constant:abc",
+            "constant:NaN",
+            "constant:-5",
+            "noisy:100",
+            "noisy:inf:1",
+            "noisy:100:NaN",
+            "kettle:1",
+            "toaster",
+        ] {
+            assert!(parse(&format!("--load {spec}")).is_err(), "{spec}");
+        }
+        for spec in ["230:x", "NaN", "-1", "inf:2", "230:-2"] {
+            assert!(parse(&format!("--voltage {spec}")).is_err(), "{spec}");
+        }
+        assert_eq!(
+            error("--load constant:NaN"),
+            "Invalid value 'NaN' for '--load'"
+        );
+        assert!(parse("--auto-reclose -1").is_err());
+    }
+}
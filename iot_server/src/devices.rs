@@ -0,0 +1,226 @@
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::DeviceInfo;
+use smart_socket::SmartDevice;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Устройство, разделяемое между соединениями
+type SharedDevice = Arc<Mutex<Box<dyn SmartDevice>>>;
+
+/// Умные устройства сервера, доступные по идентификатору
+///
+/// Каждое устройство блокируется отдельно, поэтому команды разным устройствам
+/// из разных соединений выполняются независимо.
+#[derive(Default)]
+pub struct DeviceRegistry {
+    devices: Mutex<BTreeMap<u8, SharedDevice>>,
+}
+
+impl DeviceRegistry {
+    /// Добавление устройства, идентификатор должен быть свободен
+    pub fn add(&self, device: Box<dyn SmartDevice>) -> Result<(), DeviceError> {
+        let mut devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+        if devices.contains_key(&device.get_id()) {
+            return Err(DeviceError::BadRequest);
+        }
+        devices.insert(device.get_id(), Arc::new(Mutex::new(device)));
+        Ok(())
+    }
+
+    /// Удаление устройства
+    ///
+    /// Возвращается после завершения действий, уже начатых над устройством
+    /// (см. `with_device`), поэтому после удаления они не могут ничего изменить.
+    pub fn remove(&self, id: u8) -> Result<(), DeviceError> {
+        let device = {
+            let mut devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+            devices.remove(&id).ok_or(DeviceError::UnknownDevice)?
+        };
+        drop(device.lock().unwrap_or_else(PoisonError::into_inner));
+        Ok(())
+    }
+
+    /// Описания всех устройств в порядке возрастания идентификаторов
+    pub fn list(&self) -> Vec<DeviceInfo> {
+        let devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+        devices
+            .values()
+            .map(|device| {
+                let device = device.lock().unwrap_or_else(PoisonError::into_inner);
+                DeviceInfo::from(&**device)
+            })
+            .collect()
+    }
+
+    /// Чтение состояния всех устройств, результаты - по идентификаторам
+    pub fn read_all<T, F>(&self, read: F) -> BTreeMap<u8, T>
+    where
+        F: Fn(&dyn SmartDevice) -> T,
+    {
+        let devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+        devices
+            .iter()
+            .map(|(id, device)| {
+                let device = device.lock().unwrap_or_else(PoisonError::into_inner);
+                (*id, read(&**device))
+            })
+            .collect()
+    }
+
+    /// Выполнение действия над устройством с идентификатором `id`
+    pub fn with_device<T, F>(&self, id: u8, action: F) -> Result<T, DeviceError>
+    where
+        F: FnOnce(&mut dyn SmartDevice) -> Result<T, DeviceError>,
+    {
+        let device = {
+            let devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+            Arc::clone(devices.get(&id).ok_or(DeviceError::UnknownDevice)?)
+        };
+        // Паника в другом соединении не должна блокировать доступ к устройству
+        let mut device = device.lock().unwrap_or_else(PoisonError::into_inner);
+        action(&mut **device)
+    }
+
+    /// Выполнение действия над несколькими устройствами сразу.
+    ///
+    /// Все найденные устройства блокируются на время действия, поэтому другие соединения
+    /// не видят промежуточного состояния. Устройства блокируются в порядке возрастания
+    /// идентификаторов, чтобы одновременные вызовы не ждали друг друга бесконечно.
+    /// Действие получает устройства в том же порядке, отсутствующие - как `None`.
+    pub fn with_devices<T, F>(&self, ids: &[u8], action: F) -> T
+    where
+        F: FnOnce(Vec<(u8, Option<&mut dyn SmartDevice>)>) -> T,
+    {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let shared: Vec<(u8, Option<SharedDevice>)> = {
+            let devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+            ids.iter()
+                .map(|id| (*id, devices.get(id).cloned()))
+                .collect()
+        };
+        let mut guards: Vec<_> = shared
+            .iter()
+            .map(|(id, device)| {
+                let guard = device
+                    .as_ref()
+                    .map(|device| device.lock().unwrap_or_else(PoisonError::into_inner));
+                (*id, guard)
+            })
+            .collect();
+        let devices = guards
+            .iter_mut()
+            .map(|(id, guard)| {
+                let device = guard
+                    .as_mut()
+                    .map(|device| -> &mut dyn SmartDevice { &mut ***device });
+                (*id, device)
+            })
+            .collect();
+        action(devices)
+    }
+
+    /// Продвижение имитации работы всех устройств на `elapsed`
+    pub fn advance(&self, elapsed: Duration) {
+        let devices: Vec<SharedDevice> = {
+            let devices = self.devices.lock().unwrap_or_else(PoisonError::into_inner);
+            devices.values().cloned().collect()
+        };
+        for device in devices {
+            let mut device = device.lock().unwrap_or_else(PoisonError::into_inner);
+            device.advance(elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_socket::{SmartDeviceKind, SmartSocket, SmartThermometer};
+
+    fn registry() -> DeviceRegistry {
+        let devices = DeviceRegistry::default();
+        devices
+            .add(Box::new(SmartThermometer::new("kitchen", 48)))
+            .unwrap();
+        devices.add(Box::new(SmartSocket::new("lamp", 47))).unwrap();
+        devices
+    }
+
+    #[test]
+    fn test_add_remove_and_list() {
+        let devices = registry();
+        assert_eq!(
+            devices.add(Box::new(SmartSocket::new("duplicate", 47))),
+            Err(DeviceError::BadRequest)
+        );
+
+        let listed: Vec<_> = devices
+            .list()
+            .into_iter()
+            .map(|info| (info.id, info.kind, info.name))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (47, SmartDeviceKind::Socket, String::from("lamp")),
+                (48, SmartDeviceKind::Thermometer, String::from("kitchen")),
+            ]
+        );
+
+        assert_eq!(devices.remove(50), Err(DeviceError::UnknownDevice));
+        devices.remove(47).unwrap();
+        assert_eq!(devices.remove(47), Err(DeviceError::UnknownDevice));
+        assert_eq!(devices.list().len(), 1);
+    }
+
+    #[test]
+    fn test_with_device() {
+        let devices = registry();
+        let name = devices.with_device(47, |device| Ok(device.get_name().to_string()));
+        assert_eq!(name.as_deref(), Ok("lamp"));
+        assert_eq!(
+            devices.with_device(50, |_| Ok(())),
+            Err(DeviceError::UnknownDevice)
+        );
+    }
+
+    #[test]
+    fn test_remove_waits_for_running_action() {
+        let devices = registry();
+        let (started, wait) = std::sync::mpsc::channel();
+        let finished = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                devices.with_device(47, |_| {
+                    started.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(100));
+                    finished.store(true, std::sync::atomic::Ordering::SeqCst);
+                    Ok(())
+                })
+            });
+            wait.recv().unwrap();
+            devices.remove(47).unwrap();
+            assert!(finished.load(std::sync::atomic::Ordering::SeqCst));
+        });
+        assert_eq!(
+            devices.with_device(47, |_| Ok(())),
+            Err(DeviceError::UnknownDevice)
+        );
+    }
+
+    #[test]
+    fn test_with_devices_sorts_and_dedups() {
+        let devices = registry();
+        let seen = devices.with_devices(&[50, 48, 47, 48], |devices| {
+            devices
+                .into_iter()
+                .map(|(id, device)| (id, device.map(|device| device.get_id())))
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(seen, [(47, Some(47)), (48, Some(48)), (50, None)]);
+    }
+}
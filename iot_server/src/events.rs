@@ -0,0 +1,411 @@
+use crate::devices::DeviceRegistry;
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::{
+    unix_seconds, CommandType, DeviceEvent, DeviceEventKind, IotMessage, Subscription,
+};
+use iot_protocol::iot_server::EventSender;
+use smart_socket::clock::Clock;
+use smart_socket::SmartDeviceStatus;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// Подписки соединений на события устройств
+///
+/// События обнаруживаются сравнением состояния устройств с предыдущей проверкой,
+/// поэтому изменения, отменённые до следующей проверки, не сообщаются.
+pub struct EventHub {
+    clock: Arc<dyn Clock>,
+    /// Идентификатор следующего соединения
+    next_session: AtomicU64,
+    subscribers: Mutex<BTreeMap<u64, Subscriber>>,
+    /// Состояние устройств при предыдущей проверке
+    snapshots: Mutex<BTreeMap<u8, Snapshot>>,
+}
+
+/// Подписка соединения
+struct Subscriber {
+    subscription: Subscription,
+    sender: EventSender,
+}
+
+/// Состояние устройства, изменения которого сообщаются подписчикам
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    status: SmartDeviceStatus,
+    power_w: f32,
+}
+
+/// Изменение состояния устройства между проверками
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    device_id: u8,
+    before: Snapshot,
+    after: Snapshot,
+}
+
+impl EventHub {
+    /// Подписки без подписчиков, время событий берётся из `clock`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            next_session: AtomicU64::new(0),
+            subscribers: Mutex::default(),
+            snapshots: Mutex::default(),
+        }
+    }
+
+    /// Подписки соединения `sender` (`None` - соединение не поддерживает события)
+    pub fn session(&self, sender: Option<EventSender>) -> Session<'_> {
+        Session {
+            hub: self,
+            id: self.next_session.fetch_add(1, Ordering::Relaxed),
+            sender,
+        }
+    }
+
+    /// Проверка состояния устройств и отправка событий подписчикам.
+    ///
+    /// События отправляются без блокировки подписок, поэтому медленный подписчик
+    /// не задерживает подписку и отписку других соединений. Подписчики, которым
+    /// не удалось отправить событие, удаляются.
+    pub fn publish(&self, devices: &DeviceRegistry) {
+        let changes = self.detect(devices);
+        if changes.is_empty() {
+            return;
+        }
+        let at = unix_seconds(self.clock.now());
+        let deliveries: Vec<(u64, EventSender, Vec<DeviceEvent>)> = {
+            let subscribers = self
+                .subscribers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            subscribers
+                .iter()
+                .map(|(session, subscriber)| {
+                    let events = changes
+                        .iter()
+                        .flat_map(|change| change.events(&subscriber.subscription, at))
+                        .collect();
+                    (*session, subscriber.sender.clone(), events)
+                })
+                .collect()
+        };
+
+        let failed: Vec<u64> = deliveries
+            .into_iter()
+            .filter(|(_, sender, events)| !events.iter().all(|event| sender.send(event).is_ok()))
+            .map(|(session, _, _)| session)
+            .collect();
+        for session in failed {
+            self.unsubscribe(session);
+        }
+    }
+
+    /// Изменения состояния устройств с предыдущей проверки
+    fn detect(&self, devices: &DeviceRegistry) -> Vec<Change> {
+        let current = devices.read_all(|device| Snapshot {
+            status: device.get_status(),
+            power_w: device.get_power_consumption(),
+        });
+        let mut snapshots = self
+            .snapshots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let previous = std::mem::replace(&mut *snapshots, current);
+        snapshots
+            .iter()
+            .filter_map(|(device_id, after)| {
+                let before = previous.get(device_id)?;
+                (before != after).then_some(Change {
+                    device_id: *device_id,
+                    before: *before,
+                    after: *after,
+                })
+            })
+            .collect()
+    }
+
+    fn subscribe(&self, session: u64, subscriber: Subscriber) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.insert(session, subscriber);
+    }
+
+    fn unsubscribe(&self, session: u64) {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.remove(&session);
+    }
+}
+
+impl Change {
+    /// События изменения для подписки `subscription`
+    fn events(&self, subscription: &Subscription, at: u64) -> Vec<DeviceEvent> {
+        if !subscription.includes(self.device_id) {
+            return Vec::new();
+        }
+        let mut kinds = Vec::new();
+        if self.before.status != self.after.status {
+            kinds.push(match self.after.status {
+                SmartDeviceStatus::PowerState(state) => DeviceEventKind::PowerState(state),
+                SmartDeviceStatus::Malfunction(fault) => DeviceEventKind::Malfunction(fault),
+            });
+        }
+        if let Some(threshold_w) = subscription.power_threshold_w {
+            let power_w = self.after.power_w;
+            match (self.before.power_w > threshold_w, power_w > threshold_w) {
+                (false, true) => kinds.push(DeviceEventKind::PowerAbove {
+                    threshold_w,
+                    power_w,
+                }),
+                (true, false) => kinds.push(DeviceEventKind::PowerBelow {
+                    threshold_w,
+                    power_w,
+                }),
+                _ => {}
+            }
+        }
+        kinds
+            .into_iter()
+            .map(|kind| DeviceEvent {
+                device_id: self.device_id,
+                at,
+                kind,
+            })
+            .collect()
+    }
+}
+
+/// Подписка одного соединения, отменяется при закрытии соединения
+pub struct Session<'a> {
+    hub: &'a EventHub,
+    id: u64,
+    sender: Option<EventSender>,
+}
+
+impl Session<'_> {
+    /// Обработка запросов `Subscribe` и `Unsubscribe`
+    pub fn dispatch(&self, request: IotMessage) -> IotMessage {
+        let device_id = request.get_id();
+        let command = request.get_command_type();
+        let result = match command {
+            CommandType::Subscribe => self.subscribe(&request),
+            CommandType::Unsubscribe => {
+                self.hub.unsubscribe(self.id);
+                Ok(())
+            }
+            _ => Err(DeviceError::UnknownCommand),
+        };
+        match result {
+            Ok(()) => IotMessage::with_payload(device_id, command, &()),
+            Err(e) => IotMessage::new(device_id, command, e.to_string()).with_status(e.into()),
+        }
+    }
+
+    /// Есть ли у соединения подписка
+    pub fn is_subscribed(&self) -> bool {
+        let subscribers = self
+            .hub
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.contains_key(&self.id)
+    }
+
+    fn subscribe(&self, request: &IotMessage) -> Result<(), DeviceError> {
+        let sender = self.sender.clone().ok_or(DeviceError::UnknownCommand)?;
+        let subscription: Subscription =
+            request.get_payload().map_err(|_| DeviceError::BadRequest)?;
+        if subscription
+            .power_threshold_w
+            .is_some_and(|threshold_w| !threshold_w.is_finite())
+        {
+            return Err(DeviceError::BadRequest);
+        }
+        self.hub.subscribe(
+            self.id,
+            Subscriber {
+                subscription,
+                sender,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.hub.unsubscribe(self.id);
+    }
+}
+
+/// Проверка состояния устройств и отправка событий каждые `interval`;
+/// первая проверка при запуске запоминает исходное состояние
+pub fn run(hub: &EventHub, devices: &DeviceRegistry, interval: Duration) {
+    loop {
+        hub.publish(devices);
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iot_protocol::iot_client::IotClient;
+    use iot_protocol::iot_server::IotServer;
+    use smart_socket::clock::SystemClock;
+    use smart_socket::faults::FaultInjection;
+    use smart_socket::load::ConstantLoad;
+    use smart_socket::{
+        SmartDeviceCommand, SmartDeviceErrorCode, SmartDevicePowerState, SmartSocket,
+    };
+    use std::collections::BTreeSet;
+
+    fn kinds(changes: &[Change], subscription: &Subscription) -> Vec<(u8, DeviceEventKind)> {
+        changes
+            .iter()
+            .flat_map(|change| change.events(subscription, 0))
+            .map(|event| (event.device_id, event.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_produce_events() {
+        let devices = DeviceRegistry::default();
+        let heater = SmartSocket::new("heater", 47).with_load(ConstantLoad::new(1500.0));
+        devices.add(Box::new(heater)).unwrap();
+        devices.add(Box::new(SmartSocket::new("lamp", 48))).unwrap();
+
+        let hub = EventHub::new(Arc::new(SystemClock));
+        assert!(hub.detect(&devices).is_empty());
+
+        let set_power = |id, state| {
+            devices
+                .with_device(id, |device| {
+                    device
+                        .handle_command(SmartDeviceCommand::SetPowerState(state))
+                        .map_err(DeviceError::from)
+                })
+                .unwrap();
+        };
+        set_power(47, SmartDevicePowerState::Enabled);
+        set_power(48, SmartDevicePowerState::Enabled);
+        let changes = hub.detect(&devices);
+
+        let everything = Subscription {
+            devices: BTreeSet::new(),
+            power_threshold_w: Some(1000.0),
+        };
+        let power_on = DeviceEventKind::PowerState(SmartDevicePowerState::Enabled);
+        assert_eq!(
+            kinds(&changes, &everything),
+            [
+                (47, power_on),
+                (
+                    47,
+                    DeviceEventKind::PowerAbove {
+                        threshold_w: 1000.0,
+                        power_w: 1500.0
+                    }
+                ),
+                (48, power_on),
+            ]
+        );
+        let lamp = Subscription {
+            devices: BTreeSet::from([48]),
+            power_threshold_w: None,
+        };
+        assert_eq!(kinds(&changes, &lamp), [(48, power_on)]);
+
+        devices
+            .with_device(47, |device| {
+                device
+                    .handle_command(SmartDeviceCommand::InjectFault(FaultInjection::Inject {
+                        fault: SmartDeviceErrorCode::Overcurrent,
+                        duration: None,
+                    }))
+                    .map_err(DeviceError::from)
+            })
+            .unwrap();
+        let changes = hub.detect(&devices);
+        assert_eq!(
+            kinds(&changes, &everything),
+            [
+                (
+                    47,
+                    DeviceEventKind::Malfunction(SmartDeviceErrorCode::Overcurrent)
+                ),
+                (
+                    47,
+                    DeviceEventKind::PowerBelow {
+                        threshold_w: 1000.0,
+                        power_w: 0.0
+                    }
+                ),
+            ]
+        );
+        assert!(hub.detect(&devices).is_empty());
+    }
+
+    #[test]
+    fn test_stalled_subscriber_is_dropped() {
+        let devices = DeviceRegistry::default();
+        for id in 1..=200 {
+            devices
+                .add(Box::new(SmartSocket::new("socket", id)))
+                .unwrap();
+        }
+        let hub = EventHub::new(Arc::new(SystemClock));
+        hub.publish(&devices);
+
+        // Клиент подписывается и не читает события
+        let server = IotServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let client = thread::spawn(move || IotClient::connect(addr).unwrap());
+        let connection = server.accept().unwrap();
+        let _client = client.join().unwrap();
+        connection
+            .set_write_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        let session = hub.session(connection.event_sender());
+        let subscription = Subscription {
+            devices: BTreeSet::new(),
+            power_threshold_w: None,
+        };
+        session.dispatch(IotMessage::with_payload(
+            0,
+            CommandType::Subscribe,
+            &subscription,
+        ));
+        assert!(session.is_subscribed());
+
+        let mut state = SmartDevicePowerState::Enabled;
+        for _ in 0..2000 {
+            for id in 1..=200 {
+                devices
+                    .with_device(id, |device| {
+                        device
+                            .handle_command(SmartDeviceCommand::SetPowerState(state))
+                            .map_err(DeviceError::from)
+                    })
+                    .unwrap();
+            }
+            hub.publish(&devices);
+            if !session.is_subscribed() {
+                return;
+            }
+            state = match state {
+                SmartDevicePowerState::Enabled => SmartDevicePowerState::Disabled,
+                SmartDevicePowerState::Disabled => SmartDevicePowerState::Enabled,
+            };
+        }
+        panic!("stalled subscriber must be dropped");
+    }
+}
@@ -0,0 +1,202 @@
+use crate::devices::DeviceRegistry;
+use iot_protocol::iot_command::CommandRegistry;
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::{
+    CommandType, DeviceGroup, GroupReport, GroupRequest, GroupResult, IotMessage,
+};
+use iot_protocol::iot_spec::{MAX_DATA_LENGTH, MAX_NAME_LENGTH};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Группы устройств (комнаты), сохранённые на сервере, по именам
+#[derive(Default)]
+pub struct GroupStore {
+    groups: Mutex<BTreeMap<String, DeviceGroup>>,
+}
+
+impl GroupStore {
+    /// Сохранение группы; группа с тем же именем заменяется.
+    /// Имя группы (не длиннее `MAX_NAME_LENGTH` байт) и список устройств
+    /// не должны быть пустыми.
+    pub fn save(&self, group: DeviceGroup) -> Result<(), DeviceError> {
+        if group.name.is_empty() || group.name.len() > MAX_NAME_LENGTH || group.devices.is_empty() {
+            return Err(DeviceError::BadRequest);
+        }
+        let mut groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+        groups.insert(group.name.clone(), group);
+        Ok(())
+    }
+
+    /// Все группы в порядке имён
+    pub fn list(&self) -> Vec<DeviceGroup> {
+        let groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+        groups.values().cloned().collect()
+    }
+
+    /// Удаление группы `name`
+    pub fn remove(&self, name: &str) -> Result<(), DeviceError> {
+        let mut groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+        groups
+            .remove(name)
+            .map(|_| ())
+            .ok_or(DeviceError::BadRequest)
+    }
+
+    /// Исключение удалённого устройства `id` из всех групп;
+    /// группы, в которых не осталось устройств, удаляются
+    pub fn remove_device(&self, id: u8) {
+        let mut groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+        groups.retain(|_, group| {
+            group.devices.remove(&id);
+            !group.devices.is_empty()
+        });
+    }
+
+    /// Обработка запроса: `GroupCommand` выполняется устройствами группы,
+    /// остальные запросы передаются реестру команд `registry`
+    pub fn dispatch(
+        &self,
+        registry: &CommandRegistry<DeviceRegistry>,
+        devices: &DeviceRegistry,
+        request: IotMessage,
+    ) -> IotMessage {
+        let device_id = request.get_id();
+        let command = request.get_command_type();
+        if command != CommandType::GroupCommand {
+            return registry.dispatch(devices, request);
+        }
+
+        let result = request
+            .get_payload()
+            .map_err(|_| DeviceError::BadRequest)
+            .and_then(|request| self.execute(&request, registry, devices));
+        match result {
+            Ok(report) => IotMessage::with_payload(device_id, command, &report),
+            Err(e) => IotMessage::new(device_id, command, e.to_string()).with_status(e.into()),
+        }
+    }
+
+    /// Выполнение команды каждым устройством группы, как если бы она была адресована ему.
+    ///
+    /// Устройства обрабатываются по очереди в порядке возрастания идентификаторов;
+    /// ошибка одного устройства не мешает выполнению команды остальными.
+    fn execute(
+        &self,
+        request: &GroupRequest,
+        registry: &CommandRegistry<DeviceRegistry>,
+        devices: &DeviceRegistry,
+    ) -> Result<GroupReport, DeviceError> {
+        let command = request.get_command_type();
+        if is_group_command(command) {
+            return Err(DeviceError::BadRequest);
+        }
+        let members = {
+            let groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+            let group = groups.get(&request.group).ok_or(DeviceError::BadRequest)?;
+            group.devices.clone()
+        };
+
+        let results = members
+            .into_iter()
+            .map(|device_id| {
+                let member_request = IotMessage::new(device_id, command, request.data.clone());
+                GroupResult::from_response(&registry.dispatch(devices, member_request))
+            })
+            .collect();
+        let report = GroupReport {
+            group: request.group.clone(),
+            results,
+        };
+
+        // Ответы всех устройств должны поместиться в одну посылку
+        let length = serde_json::to_string(&report).map_or(usize::MAX, |data| data.len());
+        if length > MAX_DATA_LENGTH {
+            return Err(DeviceError::BadRequest);
+        }
+        Ok(report)
+    }
+}
+
+/// Команды управления группами, которые нельзя адресовать группе
+fn is_group_command(command: CommandType) -> bool {
+    matches!(
+        command,
+        CommandType::SaveGroup
+            | CommandType::ListGroups
+            | CommandType::DeleteGroup
+            | CommandType::GroupCommand
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler;
+    use crate::scheduler::Scheduler;
+    use iot_protocol::iot_message::{ResponseStatus, StatusReport};
+    use smart_socket::clock::SystemClock;
+    use smart_socket::{SmartDeviceErrorCode, SmartDevicePowerState, SmartSocket};
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+
+    fn group_command(group: &str, command: CommandType) -> IotMessage {
+        let request = GroupRequest::new(group, command, String::new());
+        IotMessage::with_payload(0, CommandType::GroupCommand, &request)
+    }
+
+    #[test]
+    fn test_group_command_is_fanned_out() {
+        let devices = DeviceRegistry::default();
+        let mut broken = SmartSocket::new("kettle", 48);
+        broken.trip(SmartDeviceErrorCode::Overcurrent);
+        devices.add(Box::new(SmartSocket::new("lamp", 47))).unwrap();
+        devices.add(Box::new(broken)).unwrap();
+
+        let groups = Arc::new(GroupStore::default());
+        let scheduler = Arc::new(Scheduler::new(Arc::new(SystemClock)));
+        let registry = handler::command_registry(None, scheduler, Arc::clone(&groups));
+        let kitchen = DeviceGroup {
+            name: String::from("kitchen"),
+            devices: BTreeSet::from([47, 48, 50]),
+        };
+        let request = IotMessage::with_payload(0, CommandType::SaveGroup, &kitchen);
+        assert_eq!(
+            groups.dispatch(&registry, &devices, request).get_status(),
+            ResponseStatus::Ok
+        );
+
+        let response = groups.dispatch(
+            &registry,
+            &devices,
+            group_command("kitchen", CommandType::SetPowerOn),
+        );
+        assert_eq!(response.get_status(), ResponseStatus::Ok);
+        let report: GroupReport = response.get_payload().unwrap();
+        let statuses: Vec<_> = report
+            .results
+            .iter()
+            .map(|result| (result.device_id, result.get_status().unwrap()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (47, ResponseStatus::Ok),
+                (
+                    48,
+                    ResponseStatus::DeviceFault(SmartDeviceErrorCode::Overcurrent)
+                ),
+                (50, ResponseStatus::UnknownDevice),
+            ]
+        );
+        let status: StatusReport = report.results[0].get_payload().unwrap();
+        assert_eq!(status.power_state, SmartDevicePowerState::Enabled);
+
+        for request in [
+            group_command("bedroom", CommandType::SetPowerOff),
+            group_command("kitchen", CommandType::GroupCommand),
+        ] {
+            let response = groups.dispatch(&registry, &devices, request);
+            assert_eq!(response.get_status(), ResponseStatus::BadRequest);
+        }
+    }
+}
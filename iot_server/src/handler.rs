@@ -0,0 +1,470 @@
+use crate::devices::DeviceRegistry;
+use crate::groups::GroupStore;
+use crate::scenes::SceneStore;
+use crate::scheduler::Scheduler;
+use iot_protocol::iot_command::CommandRegistry;
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::{
+    CommandType, DeviceGroup, EnergyReport, FaultInjectionRequest, IotMessage, NewDevice, NewTimer,
+    PowerReport, Scene, StatusReport, TelemetryReport, TemperatureReport, TripRecord,
+};
+use iot_protocol::iot_spec::MAX_NAME_LENGTH;
+use smart_socket::electrical::ElectricalLimits;
+use smart_socket::faults::FaultInjection;
+use smart_socket::schedule::WeeklySchedule;
+use smart_socket::{
+    SmartDevice, SmartDeviceCommand, SmartDeviceKind, SmartDevicePowerState, SmartSocket,
+    SmartThermometer, TemperatureThresholds,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Реестр команд умного дома, общего для всех соединений
+///
+/// Ответ на команды управления питанием - состояние устройства после их выполнения.
+/// Команда `InjectFault` доступна только с токеном `admin_token`; если токен
+/// не задан, она всегда отклоняется. Таймеры и расписания хранятся в `scheduler`,
+/// группы устройств - в `groups` (команды группам выполняет `GroupStore::dispatch`).
+/// Удалённое устройство исключается из таймеров, расписаний, групп и сцен.
+pub fn command_registry(
+    admin_token: Option<String>,
+    scheduler: Arc<Scheduler>,
+    groups: Arc<GroupStore>,
+) -> CommandRegistry<DeviceRegistry> {
+    let mut registry = CommandRegistry::new();
+    register_schedules(&mut registry, scheduler.clone());
+    let scenes = Arc::new(SceneStore::default());
+    register_scenes(&mut registry, scenes.clone());
+    register_groups(&mut registry, groups.clone());
+    registry
+        .register(CommandType::SetPowerOn, |devices, req| {
+            set_power_state(devices, req, SmartDevicePowerState::Enabled)
+        })
+        .register(CommandType::SetPowerOff, |devices, req| {
+            set_power_state(devices, req, SmartDevicePowerState::Disabled)
+        })
+        .register(CommandType::GetStatus, |devices, req| {
+            devices.with_device(req.get_id(), |device| Ok(StatusReport::from(&*device)))
+        })
+        .register(CommandType::GetPower, |devices, req| {
+            devices.with_device(req.get_id(), |device| {
+                Ok(PowerReport {
+                    power_consumption_w: device.get_power_consumption(),
+                })
+            })
+        })
+        .register(CommandType::Rename, |devices, req| {
+            let name: String = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            if name.len() > MAX_NAME_LENGTH {
+                return Err(DeviceError::BadRequest);
+            }
+            devices.with_device(req.get_id(), |device| {
+                device.set_name(&name);
+                Ok(())
+            })
+        })
+        .register(CommandType::GetEnergy, |devices, req| {
+            devices.with_device(req.get_id(), |device| energy_report(device))
+        })
+        .register(CommandType::ResetEnergy, |devices, req| {
+            devices.with_device(req.get_id(), |device| {
+                device.handle_command(SmartDeviceCommand::ResetEnergy)?;
+                energy_report(device)
+            })
+        })
+        .register(CommandType::GetTelemetry, |devices, req| {
+            devices.with_device(req.get_id(), |device| telemetry_report(device))
+        })
+        .register(CommandType::SetLimits, |devices, req| {
+            let limits: ElectricalLimits =
+                req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            devices.with_device(req.get_id(), |device| {
+                device.handle_command(SmartDeviceCommand::SetElectricalLimits(limits))?;
+                telemetry_report(device)
+            })
+        })
+        .register(CommandType::ResetFault, |devices, req| {
+            devices.with_device(req.get_id(), |device| {
+                device.handle_command(SmartDeviceCommand::ResetFault)?;
+                Ok(StatusReport::from(&*device))
+            })
+        })
+        .register(CommandType::GetTripHistory, |devices, req| {
+            devices.with_device(req.get_id(), |device| {
+                let history = device
+                    .get_trip_history()
+                    .ok_or(DeviceError::UnknownCommand)?;
+                Ok(history
+                    .into_iter()
+                    .map(TripRecord::from)
+                    .collect::<Vec<_>>())
+            })
+        })
+        .register(CommandType::InjectFault, move |devices, req| {
+            let request: FaultInjectionRequest =
+                req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            if admin_token.as_deref() != Some(request.token.as_str()) {
+                return Err(DeviceError::Unauthorized);
+            }
+            let injection = FaultInjection::try_from(request.action)?;
+            devices.with_device(req.get_id(), |device| {
+                device.handle_command(SmartDeviceCommand::InjectFault(injection))?;
+                Ok(StatusReport::from(&*device))
+            })
+        })
+        .register(CommandType::ListDevices, |devices, _| Ok(devices.list()))
+        .register(CommandType::AddDevice, |devices, req| {
+            let new_device: NewDevice = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            if new_device.name.len() > MAX_NAME_LENGTH {
+                return Err(DeviceError::BadRequest);
+            }
+            devices.add(create_device(new_device, req.get_id()))
+        })
+        .register(CommandType::RemoveDevice, move |devices, req| {
+            devices.remove(req.get_id())?;
+            scheduler.remove_device(req.get_id());
+            groups.remove_device(req.get_id());
+            scenes.remove_device(req.get_id());
+            Ok(())
+        })
+        .register(CommandType::GetTemperature, |devices, req| {
+            devices.with_device(req.get_id(), |device| temperature_report(device))
+        })
+        .register(CommandType::SetThresholds, |devices, req| {
+            let thresholds: TemperatureThresholds =
+                req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            devices.with_device(req.get_id(), |device| {
+                device.handle_command(SmartDeviceCommand::SetTemperatureThresholds(thresholds))?;
+                temperature_report(device)
+            })
+        });
+    registry
+}
+
+/// Регистрация команд таймеров и расписаний
+fn register_schedules(registry: &mut CommandRegistry<DeviceRegistry>, scheduler: Arc<Scheduler>) {
+    let timers = Arc::clone(&scheduler);
+    let schedules = Arc::clone(&scheduler);
+    let listed = Arc::clone(&scheduler);
+    registry
+        .register(CommandType::AddTimer, move |devices, req| {
+            let timer: NewTimer = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            let delay = Duration::from_secs(timer.delay_s);
+            // Таймер добавляется под блокировкой устройства, чтобы одновременное
+            // удаление устройства не оставило таймер без устройства
+            devices.with_device(req.get_id(), |_| {
+                timers.add_timer(req.get_id(), delay, timer.action)
+            })
+        })
+        .register(CommandType::AddSchedule, move |devices, req| {
+            let schedule: WeeklySchedule =
+                req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            devices.with_device(req.get_id(), |_| {
+                schedules.add_weekly(req.get_id(), schedule)
+            })
+        })
+        .register(CommandType::ListSchedules, move |devices, req| {
+            devices.with_device(req.get_id(), |_| Ok(listed.list(req.get_id())))
+        })
+        .register(CommandType::DeleteSchedule, move |_, req| {
+            let id: u32 = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            scheduler.remove(req.get_id(), id)
+        });
+}
+
+/// Регистрация команд сцен
+fn register_scenes(registry: &mut CommandRegistry<DeviceRegistry>, scenes: Arc<SceneStore>) {
+    let saved = Arc::clone(&scenes);
+    let listed = Arc::clone(&scenes);
+    registry
+        .register(CommandType::SaveScene, move |_, req| {
+            let scene: Scene = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            saved.save(scene)
+        })
+        .register(CommandType::ListScenes, move |_, _| Ok(listed.list()))
+        .register(CommandType::ApplyScene, move |devices, req| {
+            let name: String = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            scenes.apply(&name, devices)
+        });
+}
+
+/// Регистрация команд управления группами устройств
+fn register_groups(registry: &mut CommandRegistry<DeviceRegistry>, groups: Arc<GroupStore>) {
+    let saved = Arc::clone(&groups);
+    let listed = Arc::clone(&groups);
+    registry
+        .register(CommandType::SaveGroup, move |_, req| {
+            let group: DeviceGroup = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            saved.save(group)
+        })
+        .register(CommandType::ListGroups, move |_, _| Ok(listed.list()))
+        .register(CommandType::DeleteGroup, move |_, req| {
+            let name: String = req.get_payload().map_err(|_| DeviceError::BadRequest)?;
+            groups.remove(&name)
+        });
+}
+
+/// Создание устройства заданного вида
+fn create_device(new_device: NewDevice, id: u8) -> Box<dyn SmartDevice> {
+    match new_device.kind {
+        SmartDeviceKind::Socket => Box::new(SmartSocket::new(&new_device.name, id)),
+        SmartDeviceKind::Thermometer => Box::new(SmartThermometer::new(&new_device.name, id)),
+    }
+}
+
+fn set_power_state(
+    devices: &DeviceRegistry,
+    req: &IotMessage,
+    state: SmartDevicePowerState,
+) -> Result<StatusReport, DeviceError> {
+    devices.with_device(req.get_id(), |device| {
+        device.handle_command(SmartDeviceCommand::SetPowerState(state))?;
+        Ok(StatusReport::from(&*device))
+    })
+}
+
+/// Показания счётчика энергии; для устройств без счётчика команда не поддерживается
+fn energy_report(device: &dyn SmartDevice) -> Result<EnergyReport, DeviceError> {
+    device
+        .get_energy()
+        .map(EnergyReport::from)
+        .ok_or(DeviceError::UnknownCommand)
+}
+
+/// Электрические показания; для устройств, не измеряющих напряжение и ток, команда не поддерживается
+fn telemetry_report(device: &dyn SmartDevice) -> Result<TelemetryReport, DeviceError> {
+    TelemetryReport::from_device(device).ok_or(DeviceError::UnknownCommand)
+}
+
+/// Показания термометра; для устройств, не измеряющих температуру, команда не поддерживается
+fn temperature_report(device: &dyn SmartDevice) -> Result<TemperatureReport, DeviceError> {
+    TemperatureReport::from_device(device).ok_or(DeviceError::UnknownCommand)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iot_protocol::iot_message::{FaultAction, ResponseStatus};
+    use smart_socket::clock::SystemClock;
+    use smart_socket::{SmartDeviceErrorCode, SmartDeviceStatus};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn registry(admin_token: Option<&str>) -> CommandRegistry<DeviceRegistry> {
+        let scheduler = Arc::new(Scheduler::new(Arc::new(SystemClock)));
+        command_registry(
+            admin_token.map(String::from),
+            scheduler,
+            Arc::new(GroupStore::default()),
+        )
+    }
+
+    fn devices() -> DeviceRegistry {
+        let devices = DeviceRegistry::default();
+        devices.add(Box::new(SmartSocket::new("lamp", 47))).unwrap();
+        devices
+            .add(Box::new(SmartThermometer::new("hall", 50)))
+            .unwrap();
+        devices
+    }
+
+    fn status(
+        registry: &CommandRegistry<DeviceRegistry>,
+        devices: &DeviceRegistry,
+        device_id: u8,
+        command: CommandType,
+    ) -> ResponseStatus {
+        let request = IotMessage::new(device_id, command, String::new());
+        registry.dispatch(devices, request).get_status()
+    }
+
+    #[test]
+    fn test_reports_depend_on_device_kind() {
+        let (registry, devices) = (registry(None), devices());
+
+        for command in [CommandType::GetEnergy, CommandType::GetTelemetry] {
+            assert_eq!(status(&registry, &devices, 47, command), ResponseStatus::Ok);
+            assert_eq!(
+                status(&registry, &devices, 50, command),
+                ResponseStatus::UnknownCommand
+            );
+        }
+        assert_eq!(
+            status(&registry, &devices, 47, CommandType::GetTemperature),
+            ResponseStatus::UnknownCommand
+        );
+        assert_eq!(
+            status(&registry, &devices, 50, CommandType::GetTemperature),
+            ResponseStatus::Ok
+        );
+    }
+
+    #[test]
+    fn test_unsupported_device_commands() {
+        let (registry, devices) = (registry(None), devices());
+
+        for command in [
+            CommandType::SetPowerOn,
+            CommandType::ResetEnergy,
+            CommandType::ResetFault,
+        ] {
+            assert_eq!(
+                status(&registry, &devices, 50, command),
+                ResponseStatus::UnknownCommand
+            );
+        }
+        let thresholds = TemperatureThresholds {
+            low_c: 5.0,
+            high_c: 30.0,
+        };
+        let request = IotMessage::with_payload(47, CommandType::SetThresholds, &thresholds);
+        assert_eq!(
+            registry.dispatch(&devices, request).get_status(),
+            ResponseStatus::UnknownCommand
+        );
+    }
+
+    #[test]
+    fn test_inject_fault_requires_admin_token() {
+        let inject = |registry: &CommandRegistry<DeviceRegistry>, devices, token: &str| {
+            let request = FaultInjectionRequest {
+                token: token.to_string(),
+                action: FaultAction::Inject {
+                    fault: SmartDeviceErrorCode::Overheat,
+                    duration_s: None,
+                },
+            };
+            let request = IotMessage::with_payload(47, CommandType::InjectFault, &request);
+            registry.dispatch(devices, request)
+        };
+        let devices = devices();
+
+        let without_token = registry(None);
+        for token in ["", "secret"] {
+            assert_eq!(
+                inject(&without_token, &devices, token).get_status(),
+                ResponseStatus::Unauthorized
+            );
+        }
+
+        let with_token = registry(Some("secret"));
+        assert_eq!(
+            inject(&with_token, &devices, "guess").get_status(),
+            ResponseStatus::Unauthorized
+        );
+        let lamp = || {
+            devices
+                .with_device(47, |device| Ok(device.get_status()))
+                .unwrap()
+        };
+        assert_eq!(
+            lamp(),
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled)
+        );
+
+        let response = inject(&with_token, &devices, "secret");
+        assert_eq!(response.get_status(), ResponseStatus::Ok);
+        let status: StatusReport = response.get_payload().unwrap();
+        assert_eq!(status.fault, Some(SmartDeviceErrorCode::Overheat));
+        assert_eq!(
+            lamp(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overheat)
+        );
+    }
+
+    #[test]
+    fn test_schedules_of_unknown_device() {
+        let (registry, devices) = (registry(None), devices());
+        let timer = NewTimer {
+            delay_s: 60,
+            action: SmartDevicePowerState::Enabled,
+        };
+        let add_timer = || {
+            let request = IotMessage::with_payload(47, CommandType::AddTimer, &timer);
+            registry.dispatch(&devices, request).get_status()
+        };
+
+        assert_eq!(add_timer(), ResponseStatus::Ok);
+        assert_eq!(
+            status(&registry, &devices, 47, CommandType::ListSchedules),
+            ResponseStatus::Ok
+        );
+        assert_eq!(
+            status(&registry, &devices, 47, CommandType::RemoveDevice),
+            ResponseStatus::Ok
+        );
+        assert_eq!(add_timer(), ResponseStatus::UnknownDevice);
+        assert_eq!(
+            status(&registry, &devices, 47, CommandType::ListSchedules),
+            ResponseStatus::UnknownDevice
+        );
+    }
+
+    #[test]
+    fn test_removed_device_leaves_groups_and_scenes() {
+        let (registry, devices) = (registry(None), devices());
+        let groups = [
+            DeviceGroup {
+                name: String::from("kitchen"),
+                devices: BTreeSet::from([47, 50]),
+            },
+            DeviceGroup {
+                name: String::from("lamp"),
+                devices: BTreeSet::from([47]),
+            },
+        ];
+        for group in &groups {
+            let request = IotMessage::with_payload(0, CommandType::SaveGroup, group);
+            assert_eq!(
+                registry.dispatch(&devices, request).get_status(),
+                ResponseStatus::Ok
+            );
+        }
+        let scene = Scene {
+            name: String::from("evening"),
+            states: BTreeMap::from([
+                (47, SmartDevicePowerState::Enabled),
+                (50, SmartDevicePowerState::Disabled),
+            ]),
+        };
+        let request = IotMessage::with_payload(0, CommandType::SaveScene, &scene);
+        assert_eq!(
+            registry.dispatch(&devices, request).get_status(),
+            ResponseStatus::Ok
+        );
+        let list = |command| {
+            let request = IotMessage::new(0, command, String::new());
+            registry.dispatch(&devices, request)
+        };
+
+        assert_eq!(
+            status(&registry, &devices, 47, CommandType::RemoveDevice),
+            ResponseStatus::Ok
+        );
+        let expected_groups = vec![DeviceGroup {
+            name: String::from("kitchen"),
+            devices: BTreeSet::from([50]),
+        }];
+        let expected_scenes = vec![Scene {
+            name: String::from("evening"),
+            states: BTreeMap::from([(50, SmartDevicePowerState::Disabled)]),
+        }];
+        let listed: Vec<DeviceGroup> = list(CommandType::ListGroups).get_payload().unwrap();
+        assert_eq!(listed, expected_groups);
+        let listed: Vec<Scene> = list(CommandType::ListScenes).get_payload().unwrap();
+        assert_eq!(listed, expected_scenes);
+
+        let new_device = NewDevice {
+            kind: SmartDeviceKind::Socket,
+            name: String::from("lamp"),
+        };
+        let request = IotMessage::with_payload(47, CommandType::AddDevice, &new_device);
+        assert_eq!(
+            registry.dispatch(&devices, request).get_status(),
+            ResponseStatus::Ok
+        );
+        let listed: Vec<DeviceGroup> = list(CommandType::ListGroups).get_payload().unwrap();
+        assert_eq!(listed, expected_groups);
+        let listed: Vec<Scene> = list(CommandType::ListScenes).get_payload().unwrap();
+        assert_eq!(listed, expected_scenes);
+    }
+}
@@ -1,58 +1,191 @@
-use iot_protocol::iot_message::{CommandType, IotMessage};
-use iot_protocol::iot_server::IotServer;
-use smart_socket::{SmartDevicePowerState, SmartSocket}; // SmartSocket
+mod config;
+mod devices;
+mod events;
+mod groups;
+mod handler;
+mod rules;
+mod scenes;
+mod scheduler;
+
+use config::ServerConfig;
+use devices::DeviceRegistry;
+use events::EventHub;
+use groups::GroupStore;
+use iot_protocol::iot_command::CommandRegistry;
+use iot_protocol::iot_error::{ReceptionError, RequestError};
+use iot_protocol::iot_message::CommandType;
+use iot_protocol::iot_server::{IotServer, PendingConnection};
+use rules::RuleEngine;
+use scheduler::Scheduler;
+use smart_socket::clock::SystemClock;
+use smart_socket::{SmartSocket, SmartThermometer};
 use std::error::Error;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // Читаем IP-адрес сервера из файла или используем значение по умолчанию.
-    let addr = String::from("127.0.0.1:55331");
-    let server = IotServer::bind(addr)?;
+    // Читаем параметры запуска из аргументов командной строки.
+    let config = ServerConfig::from_args(std::env::args().skip(1))?;
+    let server = IotServer::bind(&config.addr)?;
 
     // Создание инстансов умных устройств для имитации управления
-    let mut my_smart_socket = SmartSocket::new("SmartSocket_1", 47);
+    let devices = Arc::new(DeviceRegistry::default());
+    let mut smart_socket = SmartSocket::new("SmartSocket_1", 47);
+    if let Some(load) = config.load {
+        smart_socket.set_load(load);
+    }
+    if let Some(supply) = config.supply {
+        smart_socket.set_supply(supply);
+    }
+    smart_socket.set_auto_reclose(config.auto_reclose);
+    for injection in config.faults {
+        smart_socket.inject_fault(injection);
+    }
+    devices.add(Box::new(smart_socket))?;
+    devices.add(Box::new(SmartThermometer::new("SmartThermometer_1", 48)))?;
+
+    // Имитация работы устройств в отдельном потоке
+    let simulated = Arc::clone(&devices);
+    let sim_interval = config.sim_interval;
+    thread::spawn(move || simulate(&simulated, sim_interval));
+
+    // Правила автоматизации проверяются при запуске и выполняются в отдельном потоке
+    if let Some(path) = &config.rules {
+        let engine = RuleEngine::from_file(path, &devices, Arc::new(SystemClock))?;
+        let automated = Arc::clone(&devices);
+        thread::spawn(move || rules::run(engine, &automated, sim_interval));
+    }
+
+    // Таймеры и расписания проверяются в отдельном потоке с тем же шагом
+    let scheduler = Arc::new(Scheduler::new(Arc::new(SystemClock)));
+    let scheduled = Arc::clone(&scheduler);
+    let controlled = Arc::clone(&devices);
+    thread::spawn(move || scheduler::run(&scheduled, &controlled, sim_interval));
+
+    // События устройств проверяются в отдельном потоке с тем же шагом
+    let events = Arc::new(EventHub::new(Arc::new(SystemClock)));
+    let published = Arc::clone(&events);
+    let monitored = Arc::clone(&devices);
+    thread::spawn(move || events::run(&published, &monitored, sim_interval));
 
-    // Обрабатываем подключения клиентов.
+    // Поддерживаемые команды и группы устройств, общие для всех соединений
+    let groups = Arc::new(GroupStore::default());
+    let registry = Arc::new(handler::command_registry(
+        config.admin_token,
+        scheduler,
+        Arc::clone(&groups),
+    ));
+
+    // Число клиентов, обслуживаемых в данный момент
+    let active_connections = Arc::new(AtomicUsize::new(0));
+
+    // Обрабатываем подключения клиентов, каждое - в отдельном потоке.
     loop {
-        let Ok(mut connection) = server.accept() else {
-            continue;
+        let pending = match server.accept_pending() {
+            Ok(pending) => pending,
+            Err(e) => {
+                eprintln!("Не удалось принять соединение: {e}");
+                continue;
+            }
         };
 
-        // Обрабатываем запрос.
-        connection.process_request(|req| {
-            let device_id = req.get_id();
-            // Парсинг сообщения, полученного от клиента
-            let command = req.get_command_type();
-            if device_id == 47 {
-                let mut response_string = "Response: ".to_string();
-                match command {
-                    CommandType::SetPowerOn => {
-                        if let Err(x) =
-                            my_smart_socket.set_power_state(SmartDevicePowerState::Enabled)
-                        {
-                            response_string.push_str(x.to_string().as_str());
-                        }
-                        response_string.push_str("smart socket has been enabled");
-                    }
-                    CommandType::SetPowerOff => {
-                        if let Err(x) =
-                            my_smart_socket.set_power_state(SmartDevicePowerState::Disabled)
-                        {
-                            response_string.push_str(x.to_string().as_str());
-                        }
-                        response_string.push_str("smart socket has been disabled");
-                    }
-                    CommandType::GetStatus => {
-                        response_string.push_str(my_smart_socket.get_text_report().as_str());
-                    }
-                }
-                IotMessage::new(device_id, command, response_string)
-            } else {
-                IotMessage::new(
-                    device_id,
-                    command,
-                    "Обращение к несуществующему устройству".to_string(),
-                )
+        if active_connections.load(Ordering::SeqCst) >= config.max_connections {
+            eprintln!("Превышен лимит соединений, клиент отключён");
+            continue;
+        }
+
+        let guard = ConnectionGuard::new(&active_connections);
+        let devices = Arc::clone(&devices);
+        let registry = Arc::clone(&registry);
+        let groups = Arc::clone(&groups);
+        let events = Arc::clone(&events);
+        let idle_timeout = config.idle_timeout;
+
+        thread::spawn(move || {
+            let _guard = guard;
+            if let Err(e) = pending.set_read_timeout(Some(idle_timeout)) {
+                eprintln!("Не удалось настроить соединение: {e}");
+                return;
             }
-        })?;
+            serve_client(pending, &registry, &groups, &events, &devices);
+        });
+    }
+}
+
+/// Обслуживание одного клиента: handshake и обработка запросов до отключения
+///
+/// Подписанный на события клиент может не отправлять запросы дольше времени ожидания,
+/// поэтому его соединение по истечении этого времени между запросами не закрывается.
+/// Истечение времени посреди запроса (`ReceptionError::PartialFrame`) закрывает соединение
+/// всегда: прочитанное начало запроса не позволяет разобрать следующие.
+fn serve_client(
+    pending: PendingConnection,
+    registry: &CommandRegistry<DeviceRegistry>,
+    groups: &GroupStore,
+    events: &EventHub,
+    devices: &DeviceRegistry,
+) {
+    let mut connection = match pending.handshake() {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Неудачный handshake: {e}");
+            return;
+        }
+    };
+
+    let session = events.session(connection.event_sender());
+
+    // Обрабатываем запросы, пока клиент не отключится.
+    loop {
+        let result = connection.serve(|req| match req.get_command_type() {
+            CommandType::Subscribe | CommandType::Unsubscribe => session.dispatch(req),
+            _ => groups.dispatch(registry, devices, req),
+        });
+        match result {
+            Err(e) if is_timeout(&e) && session.is_subscribed() => continue,
+            Err(e) => eprintln!("Соединение с клиентом прервано: {e}"),
+            Ok(()) => {}
+        }
+        return;
+    }
+}
+
+/// Ошибка вызвана истечением времени ожидания запроса до его начала
+fn is_timeout(error: &RequestError) -> bool {
+    matches!(
+        error,
+        RequestError::Recv(ReceptionError::Io(e))
+            if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+    )
+}
+
+/// Имитация работы устройств: продвижение на фактически прошедшее время каждые `interval`
+fn simulate(devices: &DeviceRegistry, interval: Duration) {
+    let mut last = Instant::now();
+    loop {
+        thread::sleep(interval);
+        let now = Instant::now();
+        devices.advance(now - last);
+        last = now;
+    }
+}
+
+/// Учёт активного соединения: счётчик уменьшается при завершении потока,
+/// в том числе при панике обработчика
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl ConnectionGuard {
+    fn new(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(Arc::clone(counter))
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
     }
 }
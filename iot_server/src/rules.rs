@@ -0,0 +1,436 @@
+use crate::devices::DeviceRegistry;
+use iot_protocol::iot_error::DeviceError;
+use serde::Deserialize;
+use smart_socket::clock::Clock;
+use smart_socket::{
+    SmartDeviceCommand, SmartDeviceErrorCode, SmartDevicePowerState, SmartDeviceStatus,
+};
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use std::{fmt, fs, io, thread};
+
+/// Правило автоматизации: если условие выполняется не меньше `for_s` секунд,
+/// устройствам из `then` устанавливается состояние питания.
+///
+/// Правило срабатывает один раз и снова готово к срабатыванию,
+/// когда условие перестаёт выполняться.
+///
+/// Пример файла правил (JSON):
+/// ```json
+/// [
+///   {
+///     "name": "kettle overload",
+///     "when": { "type": "power_above", "device": 47, "watts": 2000 },
+///     "for_s": 300,
+///     "then": { "devices": [48], "power": "Disabled" }
+///   },
+///   {
+///     "name": "overheat",
+///     "when": { "type": "fault", "device": 48, "fault": "Overheat" },
+///     "then": { "devices": [47], "power": "Disabled" }
+///   }
+/// ]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Имя правила, уникальное в файле
+    pub name: String,
+    /// Условие
+    pub when: Condition,
+    /// Сколько секунд условие должно выполняться непрерывно
+    #[serde(default)]
+    pub for_s: u64,
+    /// Действие
+    pub then: Action,
+}
+
+/// Условие правила над показаниями и состоянием устройства
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Condition {
+    /// Потребляемая мощность выше `watts`, Вт
+    PowerAbove { device: u8, watts: f32 },
+    /// Потребляемая мощность ниже `watts`, Вт
+    PowerBelow { device: u8, watts: f32 },
+    /// Температура выше `celsius`, °C
+    TemperatureAbove { device: u8, celsius: f32 },
+    /// Температура ниже `celsius`, °C
+    TemperatureBelow { device: u8, celsius: f32 },
+    /// Устройство неисправно (`fault` не задан - любая неисправность)
+    Fault {
+        device: u8,
+        #[serde(default)]
+        fault: Option<SmartDeviceErrorCode>,
+    },
+    /// Устройство находится в состоянии питания `state`
+    PowerState {
+        device: u8,
+        state: SmartDevicePowerState,
+    },
+}
+
+/// Действие правила
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    /// Устройства, которым устанавливается состояние питания
+    pub devices: Vec<u8>,
+    /// Устанавливаемое состояние питания
+    pub power: SmartDevicePowerState,
+}
+
+impl Condition {
+    /// Устройство, показания которого проверяются
+    fn device(&self) -> u8 {
+        match self {
+            Self::PowerAbove { device, .. }
+            | Self::PowerBelow { device, .. }
+            | Self::TemperatureAbove { device, .. }
+            | Self::TemperatureBelow { device, .. }
+            | Self::Fault { device, .. }
+            | Self::PowerState { device, .. } => *device,
+        }
+    }
+
+    /// Порог условия, если есть
+    fn threshold(&self) -> Option<f32> {
+        match self {
+            Self::PowerAbove { watts, .. } | Self::PowerBelow { watts, .. } => Some(*watts),
+            Self::TemperatureAbove { celsius, .. } | Self::TemperatureBelow { celsius, .. } => {
+                Some(*celsius)
+            }
+            Self::Fault { .. } | Self::PowerState { .. } => None,
+        }
+    }
+
+    /// Проверка условия по показаниям устройства
+    fn holds(&self, reading: &Reading) -> bool {
+        match *self {
+            Self::PowerAbove { watts, .. } => reading.power_w > watts,
+            Self::PowerBelow { watts, .. } => reading.power_w < watts,
+            Self::TemperatureAbove { celsius, .. } => {
+                reading.temperature_c.is_some_and(|t| t > celsius)
+            }
+            Self::TemperatureBelow { celsius, .. } => {
+                reading.temperature_c.is_some_and(|t| t < celsius)
+            }
+            Self::Fault { fault, .. } => match reading.status {
+                SmartDeviceStatus::Malfunction(code) => fault.is_none_or(|f| f == code),
+                SmartDeviceStatus::PowerState(_) => false,
+            },
+            Self::PowerState { state, .. } => {
+                reading.status == SmartDeviceStatus::PowerState(state)
+            }
+        }
+    }
+}
+
+/// Показания устройства, по которым проверяются условия
+struct Reading {
+    power_w: f32,
+    temperature_c: Option<f32>,
+    status: SmartDeviceStatus,
+}
+
+/// Ошибка загрузки правил
+#[derive(Debug)]
+pub enum RulesError {
+    /// Ошибка чтения файла
+    Io(io::Error),
+
+    /// Файл не является списком правил в формате JSON
+    Parse(serde_json::Error),
+
+    /// Правило не прошло проверку
+    Invalid { rule: String, reason: String },
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RulesError::Io(e) => write!(f, "Internal IO error occured: {}", e),
+            RulesError::Parse(e) => write!(f, "invalid rules file: {}", e),
+            RulesError::Invalid { rule, reason } => write!(f, "rule '{rule}': {reason}"),
+        }
+    }
+}
+
+impl From<io::Error> for RulesError {
+    fn from(e: io::Error) -> Self {
+        RulesError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for RulesError {
+    fn from(e: serde_json::Error) -> Self {
+        RulesError::Parse(e)
+    }
+}
+
+impl Error for RulesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RulesError::Io(e) => Some(e),
+            RulesError::Parse(e) => Some(e),
+            RulesError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Правило и его состояние
+struct RuleState {
+    rule: Rule,
+    /// Момент, с которого условие выполняется непрерывно
+    since: Option<SystemTime>,
+    /// Правило сработало и ждёт, пока условие перестанет выполняться
+    fired: bool,
+}
+
+/// Движок правил автоматизации
+///
+/// Правила ссылаются на устройства по идентификаторам, которые проверяются только при запуске.
+/// Пока устройство удалено, условие с ним не выполняется, а команды ему не выполняются;
+/// устройство, снова добавленное с тем же идентификатором, управляется правилами как прежде.
+pub struct RuleEngine {
+    clock: Arc<dyn Clock>,
+    rules: Vec<RuleState>,
+}
+
+impl RuleEngine {
+    /// Движок с правилами `rules`, проверенными по набору устройств `devices`
+    pub fn new(
+        rules: Vec<Rule>,
+        devices: &DeviceRegistry,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, RulesError> {
+        validate(&rules, devices)?;
+        let rules = rules
+            .into_iter()
+            .map(|rule| RuleState {
+                rule,
+                since: None,
+                fired: false,
+            })
+            .collect();
+        Ok(Self { clock, rules })
+    }
+
+    /// Чтение правил из JSON-файла и их проверка
+    pub fn from_file<P: AsRef<std::path::Path>>(
+        path: P,
+        devices: &DeviceRegistry,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, RulesError> {
+        let rules = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Self::new(rules, devices, clock)
+    }
+
+    /// Проверка условий; возвращает команды сработавших правил:
+    /// (имя правила, устройство, состояние питания)
+    pub fn evaluate(
+        &mut self,
+        devices: &DeviceRegistry,
+    ) -> Vec<(String, u8, SmartDevicePowerState)> {
+        let now = self.clock.now();
+        let mut commands = Vec::new();
+        for state in &mut self.rules {
+            let holds = devices
+                .with_device(state.rule.when.device(), |device| {
+                    Ok(Reading {
+                        power_w: device.get_power_consumption(),
+                        temperature_c: device.get_temperature(),
+                        status: device.get_status(),
+                    })
+                })
+                .is_ok_and(|reading| state.rule.when.holds(&reading));
+
+            if !holds {
+                state.since = None;
+                state.fired = false;
+                continue;
+            }
+            let since = *state.since.get_or_insert(now);
+            let held = now.duration_since(since).unwrap_or(Duration::ZERO);
+            if !state.fired && held >= Duration::from_secs(state.rule.for_s) {
+                state.fired = true;
+                commands.extend(
+                    state
+                        .rule
+                        .then
+                        .devices
+                        .iter()
+                        .map(|id| (state.rule.name.clone(), *id, state.rule.then.power)),
+                );
+            }
+        }
+        commands
+    }
+}
+
+/// Проверка правил: уникальные непустые имена, существующие устройства, конечные пороги
+fn validate(rules: &[Rule], devices: &DeviceRegistry) -> Result<(), RulesError> {
+    let known: HashSet<u8> = devices.list().iter().map(|device| device.id).collect();
+    let mut names = HashSet::new();
+    for rule in rules {
+        let invalid = |reason: String| RulesError::Invalid {
+            rule: rule.name.clone(),
+            reason,
+        };
+        if rule.name.is_empty() {
+            return Err(invalid(String::from("name must not be empty")));
+        }
+        if !names.insert(rule.name.as_str()) {
+            return Err(invalid(String::from("duplicate rule name")));
+        }
+        if rule
+            .when
+            .threshold()
+            .is_some_and(|threshold| !threshold.is_finite())
+        {
+            return Err(invalid(String::from("threshold must be finite")));
+        }
+        if rule.then.devices.is_empty() {
+            return Err(invalid(String::from("no devices to control")));
+        }
+        let referenced = std::iter::once(rule.when.device()).chain(rule.then.devices.clone());
+        for id in referenced {
+            if !known.contains(&id) {
+                return Err(invalid(format!("unknown device {id}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Цикл движка правил: каждые `interval` проверяет условия и выполняет команды
+pub fn run(mut engine: RuleEngine, devices: &DeviceRegistry, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+        for (rule, device_id, power) in engine.evaluate(devices) {
+            let result = devices.with_device(device_id, |device| {
+                device
+                    .handle_command(SmartDeviceCommand::SetPowerState(power))
+                    .map_err(DeviceError::from)
+            });
+            if let Err(e) = result {
+                eprintln!("Правило '{rule}' не выполнено для устройства {device_id}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_socket::clock::ManualClock;
+    use smart_socket::load::ConstantLoad;
+    use smart_socket::{SmartSocket, SmartThermometer};
+
+    const RULES: &str = r#"[
+        {
+            "name": "kettle overload",
+            "when": { "type": "power_above", "device": 47, "watts": 2000 },
+            "for_s": 300,
+            "then": { "devices": [48], "power": "Disabled" }
+        },
+        {
+            "name": "overheat",
+            "when": { "type": "fault", "device": 49, "fault": "Overheat" },
+            "then": { "devices": [47, 48], "power": "Disabled" }
+        }
+    ]"#;
+
+    fn devices() -> DeviceRegistry {
+        let devices = DeviceRegistry::default();
+        let mut kettle = SmartSocket::new("kettle", 47).with_load(ConstantLoad::new(2200.0));
+        kettle
+            .set_power_state(SmartDevicePowerState::Enabled)
+            .unwrap();
+        let mut lamp = SmartSocket::new("lamp", 48);
+        lamp.set_power_state(SmartDevicePowerState::Enabled)
+            .unwrap();
+        devices.add(Box::new(kettle)).unwrap();
+        devices.add(Box::new(lamp)).unwrap();
+        devices
+            .add(Box::new(SmartThermometer::new("kitchen", 49)))
+            .unwrap();
+        devices
+    }
+
+    fn engine(devices: &DeviceRegistry) -> (Arc<ManualClock>, RuleEngine) {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let rules = serde_json::from_str(RULES).unwrap();
+        let engine = RuleEngine::new(rules, devices, clock.clone()).unwrap();
+        (clock, engine)
+    }
+
+    #[test]
+    fn test_rule_fires_after_duration_once() {
+        let devices = devices();
+        let (clock, mut engine) = engine(&devices);
+
+        assert!(engine.evaluate(&devices).is_empty());
+        clock.advance(Duration::from_secs(299));
+        assert!(engine.evaluate(&devices).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            engine.evaluate(&devices),
+            [(
+                String::from("kettle overload"),
+                48,
+                SmartDevicePowerState::Disabled
+            )]
+        );
+        clock.advance(Duration::from_secs(600));
+        assert!(engine.evaluate(&devices).is_empty());
+    }
+
+    #[test]
+    fn test_fault_condition() {
+        let devices = devices();
+        let (_, mut engine) = engine(&devices);
+
+        devices
+            .with_device(49, |device| {
+                let thresholds = smart_socket::TemperatureThresholds {
+                    low_c: 0.0,
+                    high_c: 10.0,
+                };
+                device.handle_command(SmartDeviceCommand::SetTemperatureThresholds(thresholds))?;
+                Ok(())
+            })
+            .unwrap();
+        let commands = engine.evaluate(&devices);
+        assert_eq!(commands.len(), 2);
+        assert!(commands.iter().all(|(rule, _, _)| rule == "overheat"));
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let devices = devices();
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        let parse = |text: &str| -> Result<RuleEngine, RulesError> {
+            RuleEngine::new(serde_json::from_str(text)?, &devices, clock.clone())
+        };
+
+        let unknown_device = r#"[{"name": "r", "when": {"type": "power_state", "device": 1,
+            "state": "Enabled"}, "then": {"devices": [47], "power": "Disabled"}}]"#;
+        assert!(matches!(
+            parse(unknown_device),
+            Err(RulesError::Invalid { .. })
+        ));
+
+        let rule = r#"{"name": "r", "when": {"type": "fault", "device": 49},
+            "then": {"devices": [47], "power": "Disabled"}}"#;
+        let duplicate = format!("[{rule}, {rule}]");
+        assert!(matches!(parse(&duplicate), Err(RulesError::Invalid { .. })));
+        assert!(parse(&format!("[{rule}]")).is_ok());
+
+        let typo = r#"[{"name": "r", "when": {"type": "power_abvoe", "device": 47,
+            "watts": 1}, "then": {"devices": [47], "power": "Disabled"}}]"#;
+        assert!(matches!(parse(typo), Err(RulesError::Parse(_))));
+    }
+}
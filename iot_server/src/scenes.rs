@@ -0,0 +1,130 @@
+use crate::devices::DeviceRegistry;
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::{Scene, SceneOutcome, SceneReport, SceneResult};
+use iot_protocol::iot_spec::MAX_NAME_LENGTH;
+use smart_socket::{SmartDeviceCommand, SmartDeviceCommandError};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Сцены, сохранённые на сервере, по именам
+#[derive(Default)]
+pub struct SceneStore {
+    scenes: Mutex<BTreeMap<String, Scene>>,
+}
+
+impl SceneStore {
+    /// Сохранение сцены; сцена с тем же именем заменяется.
+    /// Имя сцены (не длиннее `MAX_NAME_LENGTH` байт) и список устройств
+    /// не должны быть пустыми.
+    pub fn save(&self, scene: Scene) -> Result<(), DeviceError> {
+        if scene.name.is_empty() || scene.name.len() > MAX_NAME_LENGTH || scene.states.is_empty() {
+            return Err(DeviceError::BadRequest);
+        }
+        let mut scenes = self.scenes.lock().unwrap_or_else(PoisonError::into_inner);
+        scenes.insert(scene.name.clone(), scene);
+        Ok(())
+    }
+
+    /// Все сцены в порядке имён
+    pub fn list(&self) -> Vec<Scene> {
+        let scenes = self.scenes.lock().unwrap_or_else(PoisonError::into_inner);
+        scenes.values().cloned().collect()
+    }
+
+    /// Исключение удалённого устройства `id` из всех сцен;
+    /// сцены, в которых не осталось устройств, удаляются
+    pub fn remove_device(&self, id: u8) {
+        let mut scenes = self.scenes.lock().unwrap_or_else(PoisonError::into_inner);
+        scenes.retain(|_, scene| {
+            scene.states.remove(&id);
+            !scene.states.is_empty()
+        });
+    }
+
+    /// Применение сцены `name` к устройствам `devices`.
+    ///
+    /// Устройства сцены блокируются на всё время применения; неисправное
+    /// или отсутствующее устройство не мешает применению к остальным.
+    pub fn apply(&self, name: &str, devices: &DeviceRegistry) -> Result<SceneReport, DeviceError> {
+        let scene = {
+            let scenes = self.scenes.lock().unwrap_or_else(PoisonError::into_inner);
+            scenes.get(name).cloned().ok_or(DeviceError::BadRequest)?
+        };
+        let ids: Vec<u8> = scene.states.keys().copied().collect();
+        let results = devices.with_devices(&ids, |devices| {
+            devices
+                .into_iter()
+                .map(|(device_id, device)| {
+                    let outcome = match device {
+                        None => SceneOutcome::UnknownDevice,
+                        Some(device) => {
+                            let state = scene.states[&device_id];
+                            match device.handle_command(SmartDeviceCommand::SetPowerState(state)) {
+                                Ok(()) => SceneOutcome::Applied,
+                                Err(SmartDeviceCommandError::Malfunction(fault)) => {
+                                    SceneOutcome::Fault(fault)
+                                }
+                                Err(_) => SceneOutcome::Unsupported,
+                            }
+                        }
+                    };
+                    SceneResult { device_id, outcome }
+                })
+                .collect()
+        });
+        Ok(SceneReport {
+            name: scene.name,
+            results,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_socket::{
+        SmartDeviceErrorCode, SmartDevicePowerState, SmartSocket, SmartThermometer,
+    };
+
+    #[test]
+    fn test_apply_reports_each_device() {
+        let devices = DeviceRegistry::default();
+        let mut broken = SmartSocket::new("broken", 48);
+        broken.trip(SmartDeviceErrorCode::Overcurrent);
+        devices.add(Box::new(SmartSocket::new("lamp", 47))).unwrap();
+        devices.add(Box::new(broken)).unwrap();
+        devices
+            .add(Box::new(SmartThermometer::new("kitchen", 49)))
+            .unwrap();
+
+        let store = SceneStore::default();
+        let states = [47, 48, 49, 50].map(|id| (id, SmartDevicePowerState::Enabled));
+        store
+            .save(Scene {
+                name: String::from("evening"),
+                states: BTreeMap::from(states),
+            })
+            .unwrap();
+
+        let report = store.apply("evening", &devices).unwrap();
+        let outcomes: Vec<_> = report.results.iter().map(|r| r.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [
+                SceneOutcome::Applied,
+                SceneOutcome::Fault(SmartDeviceErrorCode::Overcurrent),
+                SceneOutcome::Unsupported,
+                SceneOutcome::UnknownDevice,
+            ]
+        );
+        assert_eq!(
+            store.apply("morning", &devices),
+            Err(DeviceError::BadRequest)
+        );
+        let long_name = Scene {
+            name: "x".repeat(MAX_NAME_LENGTH + 1),
+            states: BTreeMap::from([(47, SmartDevicePowerState::Enabled)]),
+        };
+        assert_eq!(store.save(long_name), Err(DeviceError::BadRequest));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::devices::DeviceRegistry;
+use iot_protocol::iot_error::DeviceError;
+use iot_protocol::iot_message::{unix_seconds, ScheduleInfo, ScheduleKind};
+use smart_socket::clock::Clock;
+use smart_socket::schedule::WeeklySchedule;
+use smart_socket::{SmartDeviceCommand, SmartDevicePowerState};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Наибольшее число таймеров и расписаний одного устройства
+pub const MAX_ENTRIES_PER_DEVICE: usize = 32;
+
+/// Таймеры обратного отсчёта и недельные расписания устройств
+///
+/// Время берётся из `Clock`, поэтому срабатывание можно проверить,
+/// переводя часы вручную, без ожидания.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Идентификатор следующей записи
+    next_id: u32,
+    entries: BTreeMap<u32, Entry>,
+}
+
+/// Запись планировщика
+struct Entry {
+    device_id: u8,
+    /// Недельное расписание, `None` - однократный таймер
+    weekly: Option<WeeklySchedule>,
+    action: SmartDevicePowerState,
+    /// Ближайшее срабатывание
+    next_at: SystemTime,
+}
+
+impl Scheduler {
+    /// Планировщик, время для которого берётся из `clock`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Таймер: установка состояния `action` устройству `device_id` через `delay`;
+    /// задержка, выходящая за пределы представимого времени, отклоняется
+    pub fn add_timer(
+        &self,
+        device_id: u8,
+        delay: Duration,
+        action: SmartDevicePowerState,
+    ) -> Result<ScheduleInfo, DeviceError> {
+        let next_at = self
+            .clock
+            .now()
+            .checked_add(delay)
+            .ok_or(DeviceError::BadRequest)?;
+        self.insert(Entry {
+            device_id,
+            weekly: None,
+            action,
+            next_at,
+        })
+    }
+
+    /// Недельное расписание устройства `device_id`, в расписании должен быть хотя бы один день
+    pub fn add_weekly(
+        &self,
+        device_id: u8,
+        schedule: WeeklySchedule,
+    ) -> Result<ScheduleInfo, DeviceError> {
+        let next_at = schedule
+            .next_after(self.clock.now())
+            .ok_or(DeviceError::BadRequest)?;
+        self.insert(Entry {
+            device_id,
+            action: schedule.action,
+            weekly: Some(schedule),
+            next_at,
+        })
+    }
+
+    /// Таймеры и расписания устройства `device_id` в порядке создания
+    pub fn list(&self, device_id: u8) -> Vec<ScheduleInfo> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.device_id == device_id)
+            .map(|(id, entry)| entry.info(*id))
+            .collect()
+    }
+
+    /// Удаление таймера или расписания `id` устройства `device_id`
+    pub fn remove(&self, device_id: u8, id: u32) -> Result<(), DeviceError> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        match entries.entries.get(&id) {
+            Some(entry) if entry.device_id == device_id => {
+                entries.entries.remove(&id);
+                Ok(())
+            }
+            _ => Err(DeviceError::BadRequest),
+        }
+    }
+
+    /// Удаление всех таймеров и расписаний устройства `device_id`
+    pub fn remove_device(&self, device_id: u8) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries
+            .entries
+            .retain(|_, entry| entry.device_id != device_id);
+    }
+
+    /// Сработавшие к текущему моменту записи: (устройство, состояние питания).
+    ///
+    /// Сработавшие таймеры удаляются, расписания переносятся на следующее срабатывание.
+    pub fn due(&self) -> Vec<(u8, SmartDevicePowerState)> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut due = Vec::new();
+        entries.entries.retain(|_, entry| {
+            if entry.next_at > now {
+                return true;
+            }
+            due.push((entry.device_id, entry.action));
+            match entry
+                .weekly
+                .as_ref()
+                .and_then(|weekly| weekly.next_after(now))
+            {
+                Some(next_at) => {
+                    entry.next_at = next_at;
+                    true
+                }
+                None => false,
+            }
+        });
+        due
+    }
+
+    /// Добавление записи, у устройства не может быть больше `MAX_ENTRIES_PER_DEVICE` записей
+    fn insert(&self, entry: Entry) -> Result<ScheduleInfo, DeviceError> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = entries
+            .entries
+            .values()
+            .filter(|existing| existing.device_id == entry.device_id)
+            .count();
+        if count >= MAX_ENTRIES_PER_DEVICE {
+            return Err(DeviceError::BadRequest);
+        }
+        entries.next_id += 1;
+        let id = entries.next_id;
+        let info = entry.info(id);
+        entries.entries.insert(id, entry);
+        Ok(info)
+    }
+}
+
+impl Entry {
+    fn info(&self, id: u32) -> ScheduleInfo {
+        let kind = match &self.weekly {
+            None => ScheduleKind::Timer,
+            Some(weekly) => ScheduleKind::Weekly {
+                days: weekly.days.clone(),
+                at: weekly.at,
+            },
+        };
+        ScheduleInfo {
+            id,
+            device_id: self.device_id,
+            kind,
+            action: self.action,
+            next_at: unix_seconds(self.next_at),
+        }
+    }
+}
+
+/// Цикл планировщика: каждые `interval` выполняет сработавшие таймеры и расписания
+pub fn run(scheduler: &Scheduler, devices: &DeviceRegistry, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+        for (device_id, action) in scheduler.due() {
+            let result = devices.with_device(device_id, |device| {
+                device
+                    .handle_command(SmartDeviceCommand::SetPowerState(action))
+                    .map_err(DeviceError::from)
+            });
+            if let Err(e) = result {
+                eprintln!("Не удалось выполнить расписание устройства {device_id}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_socket::clock::ManualClock;
+    use smart_socket::schedule::{TimeOfDay, Weekday};
+
+    /// 2026-10-16 06:30:00 UTC, пятница
+    const FRIDAY_MORNING: Duration = Duration::from_secs(1_792_132_200);
+
+    fn scheduler() -> (Arc<ManualClock>, Scheduler) {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + FRIDAY_MORNING));
+        (clock.clone(), Scheduler::new(clock))
+    }
+
+    #[test]
+    fn test_timer_fires_once() {
+        let (clock, scheduler) = scheduler();
+        let timer = scheduler
+            .add_timer(
+                47,
+                Duration::from_secs(30 * 60),
+                SmartDevicePowerState::Disabled,
+            )
+            .unwrap();
+        assert_eq!(timer.kind, ScheduleKind::Timer);
+
+        clock.advance(Duration::from_secs(29 * 60));
+        assert!(scheduler.due().is_empty());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(scheduler.due(), [(47, SmartDevicePowerState::Disabled)]);
+        assert!(scheduler.due().is_empty());
+        assert!(scheduler.list(47).is_empty());
+
+        assert_eq!(
+            scheduler.add_timer(
+                47,
+                Duration::from_secs(u64::MAX),
+                SmartDevicePowerState::Enabled
+            ),
+            Err(DeviceError::BadRequest)
+        );
+        assert!(scheduler.list(47).is_empty());
+    }
+
+    #[test]
+    fn test_weekly_schedule_repeats() {
+        let (clock, scheduler) = scheduler();
+        let schedule = WeeklySchedule {
+            days: Weekday::WEEKDAYS.to_vec(),
+            at: TimeOfDay::new(7, 0).unwrap(),
+            action: SmartDevicePowerState::Enabled,
+        };
+        let info = scheduler.add_weekly(47, schedule).unwrap();
+        assert_eq!(scheduler.list(47), std::slice::from_ref(&info));
+
+        clock.advance(Duration::from_secs(30 * 60));
+        assert_eq!(scheduler.due(), [(47, SmartDevicePowerState::Enabled)]);
+
+        // Следующее срабатывание - в понедельник
+        let next = &scheduler.list(47)[0];
+        assert_eq!(next.next_at, info.next_at + 3 * 24 * 3600);
+        clock.advance(Duration::from_secs(2 * 24 * 3600));
+        assert!(scheduler.due().is_empty());
+
+        assert_eq!(scheduler.remove(48, info.id), Err(DeviceError::BadRequest));
+        scheduler.remove(47, info.id).unwrap();
+        assert!(scheduler.list(47).is_empty());
+    }
+
+    #[test]
+    fn test_entries_per_device_are_limited() {
+        let (_, scheduler) = scheduler();
+        let add = |device_id| {
+            scheduler.add_timer(
+                device_id,
+                Duration::from_secs(60),
+                SmartDevicePowerState::Enabled,
+            )
+        };
+        for _ in 0..MAX_ENTRIES_PER_DEVICE {
+            add(47).unwrap();
+        }
+        assert_eq!(add(47), Err(DeviceError::BadRequest));
+        add(48).unwrap();
+
+        let first = scheduler.list(47)[0].id;
+        scheduler.remove(47, first).unwrap();
+        add(47).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Источники текущего времени для учёта энергии и расписаний.
+//!
+//! Устройства получают время через `Clock`, поэтому в тестах его можно подменить на `ManualClock`.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// Источник текущего времени
+pub trait Clock: Send + Sync {
+    /// Текущее время
+    fn now(&self) -> SystemTime;
+}
+
+/// Системные часы
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Часы, время которых переводится вручную
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Часы, показывающие время `now`
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Перевод часов вперёд на `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+
+    /// Установка времени `now`
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Календарная дата по UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    /// Год
+    pub year: i32,
+    /// Месяц, `1..=12`
+    pub month: u8,
+    /// День месяца, `1..=31`
+    pub day: u8,
+}
+
+impl Date {
+    /// Дата момента `time` по UTC
+    pub fn of(time: SystemTime) -> Self {
+        Self::from_days(days_since_epoch(time))
+    }
+
+    /// Дата по числу дней от 1970-01-01
+    pub fn from_days(days: u64) -> Self {
+        // Алгоритм civil_from_days (H. Hinnant)
+        let z = days as i64 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Self { year, month, day }
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Длительность суток
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Число полных суток от 1970-01-01 до момента `time` (моменты до 1970 года считаются нулевыми сутками)
+pub fn days_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / DAY.as_secs())
+}
+
+/// Начало суток по UTC, следующих за моментом `time`
+pub fn next_midnight(time: SystemTime) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs((days_since_epoch(time) + 1) * DAY.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_from_days() {
+        assert_eq!(Date::from_days(0).to_string(), "1970-01-01");
+        assert_eq!(Date::from_days(19_782).to_string(), "2024-02-29");
+        assert_eq!(Date::from_days(20_744).to_string(), "2026-10-18");
+    }
+
+    #[test]
+    fn test_next_midnight() {
+        let noon = SystemTime::UNIX_EPOCH + DAY * 3 + DAY / 2;
+        assert_eq!(next_midnight(noon), SystemTime::UNIX_EPOCH + DAY * 4);
+        assert_eq!(
+            next_midnight(SystemTime::UNIX_EPOCH + DAY * 4),
+            SystemTime::UNIX_EPOCH + DAY * 5
+        );
+    }
+}
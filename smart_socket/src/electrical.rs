@@ -0,0 +1,183 @@
+//! Электрические параметры сети и нагрузки умной розетки.
+
+use crate::load::{non_negative, Noise};
+
+/// Номинальное напряжение сети, В
+pub const NOMINAL_VOLTAGE_V: f32 = 230.0;
+
+/// Модель напряжения сети: номинал со случайными колебаниями
+#[derive(Debug, Clone)]
+pub struct LineSupply {
+    /// Среднее напряжение, В
+    nominal_v: f32,
+    /// Наибольшее отклонение от среднего напряжения, В
+    amplitude_v: f32,
+    noise: Noise,
+}
+
+impl LineSupply {
+    /// Сеть с напряжением `nominal_v ± amplitude_v`, В;
+    /// отрицательные и нечисловые значения заменяются нулём
+    pub fn new(nominal_v: f32, amplitude_v: f32) -> Self {
+        Self {
+            nominal_v: non_negative(nominal_v),
+            amplitude_v: non_negative(amplitude_v),
+            noise: Noise::default(),
+        }
+    }
+
+    /// Задание начального значения генератора для воспроизводимых последовательностей
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Noise::with_seed(seed);
+        self
+    }
+
+    /// Очередной замер напряжения, В
+    pub fn voltage(&mut self) -> f32 {
+        let noise = self.noise.next();
+        (self.nominal_v + self.amplitude_v * noise).max(0.0)
+    }
+}
+
+/// Сеть 230 В ± 2 В
+impl Default for LineSupply {
+    fn default() -> Self {
+        Self::new(NOMINAL_VOLTAGE_V, 2.0)
+    }
+}
+
+/// Показания измерителя розетки
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElectricalReadings {
+    /// Напряжение сети, В
+    pub voltage_v: f32,
+    /// Ток нагрузки, А
+    pub current_a: f32,
+    /// Коэффициент мощности нагрузки
+    pub power_factor: f32,
+}
+
+impl ElectricalReadings {
+    /// Показания для активной мощности `power_w`, Вт, при напряжении `voltage_v`, В
+    pub fn new(voltage_v: f32, power_w: f32, power_factor: f32) -> Self {
+        let current_a = if voltage_v > 0.0 && power_factor > 0.0 {
+            power_w / (voltage_v * power_factor)
+        } else {
+            0.0
+        };
+        Self {
+            voltage_v,
+            current_a,
+            power_factor,
+        }
+    }
+}
+
+/// Пределы, при превышении которых розетка отключается с неисправностью
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElectricalLimits {
+    /// Наибольшее напряжение, В: выше него - `SmartDeviceErrorCode::Overvoltage`
+    pub max_voltage_v: f32,
+    /// Наибольший ток, А: выше него - `SmartDeviceErrorCode::Overcurrent`
+    pub max_current_a: f32,
+}
+
+impl ElectricalLimits {
+    /// Пределы положительны и конечны
+    pub fn is_valid(&self) -> bool {
+        [self.max_voltage_v, self.max_current_a]
+            .iter()
+            .all(|limit| limit.is_finite() && *limit > 0.0)
+    }
+}
+
+/// Номинал +10% по напряжению, 16 А по току
+impl Default for ElectricalLimits {
+    fn default() -> Self {
+        Self {
+            max_voltage_v: 253.0,
+            max_current_a: 16.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::ConstantLoad;
+    use crate::{SmartDeviceErrorCode, SmartDevicePowerState, SmartDeviceStatus, SmartSocket};
+
+    fn test_socket(power_w: f32, voltage_v: f32) -> SmartSocket {
+        SmartSocket::new("test", 1)
+            .with_load(ConstantLoad::new(power_w))
+            .with_supply(LineSupply::new(voltage_v, 0.0))
+    }
+
+    #[test]
+    fn test_current_from_power() {
+        let readings = ElectricalReadings::new(230.0, 920.0, 0.8);
+        assert_eq!(readings.current_a, 5.0);
+        assert_eq!(ElectricalReadings::new(0.0, 920.0, 1.0).current_a, 0.0);
+
+        let mut supply = LineSupply::new(230.0, 5.0).with_seed(3);
+        assert!((0..1000).all(|_| (225.0..=235.0).contains(&supply.voltage())));
+        assert_eq!(LineSupply::new(f32::NAN, -5.0).voltage(), 0.0);
+    }
+
+    #[test]
+    fn test_faults_raised_on_limits() {
+        let mut socket = test_socket(1000.0, 230.0);
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Ok(())
+        );
+        assert!((socket.get_readings().current_a - 1000.0 / 230.0).abs() < 1e-4);
+
+        socket.set_supply(LineSupply::new(260.0, 0.0));
+        assert_eq!(
+            socket.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overvoltage)
+        );
+        assert_eq!(socket.get_power_consumption(), 0.0);
+
+        let mut socket = test_socket(5000.0, 230.0);
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Err(SmartDeviceErrorCode::Overcurrent)
+        );
+
+        // Выключенная розетка не отключается по напряжению
+        let mut socket = test_socket(100.0, 300.0);
+        socket.advance(std::time::Duration::from_secs(1));
+        assert_eq!(
+            socket.get_status(),
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled)
+        );
+    }
+
+    #[test]
+    fn test_lowered_limits_trip_socket() {
+        let mut socket = test_socket(1000.0, 230.0);
+        socket
+            .set_power_state(SmartDevicePowerState::Enabled)
+            .unwrap();
+
+        let limits = ElectricalLimits {
+            max_voltage_v: 253.0,
+            max_current_a: 2.0,
+        };
+        assert!(socket
+            .set_limits(ElectricalLimits {
+                max_current_a: f32::NAN,
+                ..limits
+            })
+            .is_err());
+        socket.set_limits(limits).unwrap();
+        assert_eq!(
+            socket.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overcurrent)
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Учёт потреблённой энергии.
+
+use crate::clock::{next_midnight, Clock, Date};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Число хранимых суточных счётчиков
+const DAILY_BUCKETS: usize = 62;
+
+/// Число хранимых месячных счётчиков
+const MONTHLY_BUCKETS: usize = 24;
+
+/// Календарный месяц по UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Month {
+    /// Год
+    pub year: i32,
+    /// Месяц, `1..=12`
+    pub month: u8,
+}
+
+impl From<Date> for Month {
+    fn from(date: Date) -> Self {
+        Self {
+            year: date.year,
+            month: date.month,
+        }
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// Счётчик энергии: интегрирует потребляемую мощность по времени.
+///
+/// Мощность считается постоянной между соседними вызовами `sample`.
+/// Кроме общего счётчика ведутся сбрасываемый счётчик и счётчики
+/// за последние сутки и месяцы (по UTC).
+#[derive(Clone)]
+pub struct EnergyMeter {
+    clock: Arc<dyn Clock>,
+    /// Момент последнего замера
+    last_sample: SystemTime,
+    /// Мощность с момента последнего замера, Вт
+    power_w: f32,
+    /// Энергия за всё время, Вт·ч
+    total_wh: f64,
+    /// Энергия с момента сброса, Вт·ч
+    since_reset_wh: f64,
+    /// Момент сброса
+    reset_at: SystemTime,
+    /// Энергия по суткам, Вт·ч
+    daily_wh: BTreeMap<Date, f64>,
+    /// Энергия по месяцам, Вт·ч
+    monthly_wh: BTreeMap<Month, f64>,
+}
+
+/// Показания счётчика энергии
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyReading {
+    /// Энергия за всё время, кВт·ч
+    pub total_kwh: f64,
+    /// Энергия с момента сброса, кВт·ч
+    pub since_reset_kwh: f64,
+    /// Момент сброса
+    pub reset_at: SystemTime,
+    /// Энергия по суткам, кВт·ч, в порядке возрастания дат
+    pub daily_kwh: Vec<(Date, f64)>,
+    /// Энергия по месяцам, кВт·ч, в порядке возрастания
+    pub monthly_kwh: Vec<(Month, f64)>,
+}
+
+impl EnergyMeter {
+    /// Счётчик, время для которого берётся из `clock`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            clock,
+            last_sample: now,
+            power_w: 0.0,
+            total_wh: 0.0,
+            since_reset_wh: 0.0,
+            reset_at: now,
+            daily_wh: BTreeMap::new(),
+            monthly_wh: BTreeMap::new(),
+        }
+    }
+
+    /// Замер: учёт энергии с момента предыдущего замера и новая мощность `power_w`, Вт
+    pub fn sample(&mut self, power_w: f32) {
+        let now = self.clock.now();
+        // Если часы переведены назад, интервал не учитывается
+        if now > self.last_sample {
+            self.accumulate(self.last_sample, now);
+        }
+        self.last_sample = now;
+        self.power_w = power_w;
+    }
+
+    /// Сброс сбрасываемого счётчика
+    pub fn reset(&mut self) {
+        self.sample(self.power_w);
+        self.since_reset_wh = 0.0;
+        self.reset_at = self.last_sample;
+    }
+
+    /// Показания счётчика на текущий момент
+    pub fn reading(&self) -> EnergyReading {
+        let mut meter = self.clone();
+        meter.sample(self.power_w);
+
+        EnergyReading {
+            total_kwh: meter.total_wh / 1000.0,
+            since_reset_kwh: meter.since_reset_wh / 1000.0,
+            reset_at: meter.reset_at,
+            daily_kwh: to_kwh(&meter.daily_wh),
+            monthly_kwh: to_kwh(&meter.monthly_wh),
+        }
+    }
+
+    /// Учёт энергии на интервале `[from, to)` с разбиением по суткам
+    fn accumulate(&mut self, mut from: SystemTime, to: SystemTime) {
+        while from < to {
+            let until = next_midnight(from).min(to);
+            let hours = until
+                .duration_since(from)
+                .unwrap_or(Duration::ZERO)
+                .as_secs_f64()
+                / 3600.0;
+            let wh = f64::from(self.power_w) * hours;
+
+            let date = Date::of(from);
+            self.total_wh += wh;
+            self.since_reset_wh += wh;
+            *self.daily_wh.entry(date).or_default() += wh;
+            *self.monthly_wh.entry(Month::from(date)).or_default() += wh;
+            from = until;
+        }
+
+        while self.daily_wh.len() > DAILY_BUCKETS {
+            self.daily_wh.pop_first();
+        }
+        while self.monthly_wh.len() > MONTHLY_BUCKETS {
+            self.monthly_wh.pop_first();
+        }
+    }
+}
+
+/// Перевод счётчиков по периодам из Вт·ч в кВт·ч
+fn to_kwh<Period: Copy>(buckets: &BTreeMap<Period, f64>) -> Vec<(Period, f64)> {
+    buckets
+        .iter()
+        .map(|(period, wh)| (*period, wh / 1000.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ManualClock, DAY};
+
+    /// 2024-01-31 23:00:00 UTC
+    const START: Duration = Duration::from_secs(1_706_742_000);
+
+    fn meter() -> (Arc<ManualClock>, EnergyMeter) {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH + START));
+        let meter = EnergyMeter::new(clock.clone());
+        (clock, meter)
+    }
+
+    fn assert_kwh(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_energy_is_split_by_day_and_month() {
+        let (clock, mut meter) = meter();
+
+        meter.sample(1000.0);
+        clock.advance(Duration::from_secs(2 * 3600));
+        let reading = meter.reading();
+
+        assert_kwh(reading.total_kwh, 2.0);
+        let date = |month, day| Date {
+            year: 2024,
+            month,
+            day,
+        };
+        assert_eq!(reading.daily_kwh.len(), 2);
+        assert_eq!(reading.daily_kwh[0].0, date(1, 31));
+        assert_kwh(reading.daily_kwh[0].1, 1.0);
+        assert_eq!(reading.daily_kwh[1].0, date(2, 1));
+        assert_kwh(reading.daily_kwh[1].1, 1.0);
+        assert_eq!(reading.monthly_kwh[0].0.to_string(), "2024-01");
+        assert_eq!(reading.monthly_kwh[1].0.to_string(), "2024-02");
+    }
+
+    #[test]
+    fn test_power_changes_and_reset() {
+        let (clock, mut meter) = meter();
+
+        meter.sample(500.0);
+        clock.advance(Duration::from_secs(3600));
+        meter.sample(0.0);
+        clock.advance(Duration::from_secs(3600));
+        assert_kwh(meter.reading().total_kwh, 0.5);
+
+        meter.reset();
+        meter.sample(2000.0);
+        clock.advance(Duration::from_secs(1800));
+        let reading = meter.reading();
+        assert_kwh(reading.total_kwh, 1.5);
+        assert_kwh(reading.since_reset_kwh, 1.0);
+        assert_eq!(
+            reading.reset_at,
+            SystemTime::UNIX_EPOCH + START + Duration::from_secs(7200)
+        );
+    }
+
+    #[test]
+    fn test_old_buckets_are_dropped() {
+        let (clock, mut meter) = meter();
+
+        meter.sample(100.0);
+        clock.advance(DAY * 100);
+        let reading = meter.reading();
+
+        assert_eq!(reading.daily_kwh.len(), DAILY_BUCKETS);
+        assert_kwh(reading.total_kwh, 240.0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Внесение неисправностей в умную розетку для проверки поведения клиентов.
+//!
+//! Внесённая неисправность сразу вызывает защитное отключение розетки и действует
+//! заданное время: пока она действует, розетка после сброса или повторного
+//! включения снова отключается. Неисправности можно вносить сразу, по расписанию
+//! или случайно с заданной вероятностью.
+
+use crate::load::Noise;
+use crate::SmartDeviceErrorCode;
+use std::time::Duration;
+
+/// Случайное возникновение неисправностей
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomFaults {
+    /// Вероятность возникновения неисправности за секунду работы, `[0.0, 1.0]`
+    pub probability: f64,
+    /// Время действия неисправности
+    pub duration: Duration,
+    /// Неисправности, из которых выбирается возникающая (пустой список - все)
+    pub faults: Vec<SmartDeviceErrorCode>,
+}
+
+/// Все коды неисправностей
+const ALL_FAULTS: [SmartDeviceErrorCode; 4] = [
+    SmartDeviceErrorCode::Overcurrent,
+    SmartDeviceErrorCode::Overvoltage,
+    SmartDeviceErrorCode::Overheat,
+    SmartDeviceErrorCode::Underheat,
+];
+
+/// Действие по внесению неисправностей
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultInjection {
+    /// Немедленная неисправность на время `duration` (`None` - до отмены)
+    Inject {
+        fault: SmartDeviceErrorCode,
+        duration: Option<Duration>,
+    },
+    /// Неисправность через `delay` на время `duration` (`None` - до отмены)
+    Schedule {
+        delay: Duration,
+        fault: SmartDeviceErrorCode,
+        duration: Option<Duration>,
+    },
+    /// Включение (`Some`) или отключение (`None`) случайных неисправностей
+    Random(Option<RandomFaults>),
+    /// Отмена действующих и запланированных неисправностей и случайных неисправностей
+    Clear,
+}
+
+/// Неисправность, запланированная на будущее
+#[derive(Debug, Clone)]
+struct ScheduledFault {
+    /// Время до возникновения
+    delay: Duration,
+    fault: SmartDeviceErrorCode,
+    duration: Option<Duration>,
+}
+
+/// Состояние внесённых неисправностей розетки
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FaultInjector {
+    /// Действующая неисправность и оставшееся время её действия (`None` - до отмены)
+    active: Option<(SmartDeviceErrorCode, Option<Duration>)>,
+    scheduled: Vec<ScheduledFault>,
+    random: Option<RandomFaults>,
+    noise: Noise,
+}
+
+impl FaultInjector {
+    /// Выполнение действия; возвращает неисправность, которая возникла немедленно
+    pub(crate) fn apply(&mut self, injection: FaultInjection) -> Option<SmartDeviceErrorCode> {
+        match injection {
+            FaultInjection::Inject { fault, duration } => {
+                self.active = Some((fault, duration));
+                return Some(fault);
+            }
+            FaultInjection::Schedule {
+                delay,
+                fault,
+                duration,
+            } => self.scheduled.push(ScheduledFault {
+                delay,
+                fault,
+                duration,
+            }),
+            FaultInjection::Random(random) => self.random = random,
+            FaultInjection::Clear => {
+                self.active = None;
+                self.scheduled.clear();
+                self.random = None;
+            }
+        }
+        None
+    }
+
+    /// Действующая неисправность
+    pub(crate) fn active(&self) -> Option<SmartDeviceErrorCode> {
+        self.active.map(|(fault, _)| fault)
+    }
+
+    /// Продвижение времени на `elapsed`; возвращает неисправность, возникшую за это время
+    pub(crate) fn advance(&mut self, elapsed: Duration) -> Option<SmartDeviceErrorCode> {
+        if let Some((_, Some(remaining))) = &mut self.active {
+            *remaining = remaining.saturating_sub(elapsed);
+            if remaining.is_zero() {
+                self.active = None;
+            }
+        }
+
+        let mut occurred = None;
+        for scheduled in &mut self.scheduled {
+            scheduled.delay = scheduled.delay.saturating_sub(elapsed);
+            if scheduled.delay.is_zero() {
+                occurred = Some((scheduled.fault, scheduled.duration));
+            }
+        }
+        self.scheduled
+            .retain(|scheduled| !scheduled.delay.is_zero());
+
+        if occurred.is_none() {
+            occurred = self.random_fault(elapsed);
+        }
+        if let Some((fault, duration)) = occurred {
+            self.active = Some((fault, duration));
+        }
+        occurred.map(|(fault, _)| fault)
+    }
+
+    /// Розыгрыш случайной неисправности за время `elapsed`
+    fn random_fault(
+        &mut self,
+        elapsed: Duration,
+    ) -> Option<(SmartDeviceErrorCode, Option<Duration>)> {
+        let random = self.random.as_ref()?;
+        let probability =
+            1.0 - (1.0 - random.probability.clamp(0.0, 1.0)).powf(elapsed.as_secs_f64());
+        if f64::from(self.noise.next() + 1.0) / 2.0 >= probability {
+            return None;
+        }
+        let faults = match random.faults.as_slice() {
+            [] => &ALL_FAULTS[..],
+            faults => faults,
+        };
+        let index = ((self.noise.next() + 1.0) / 2.0 * faults.len() as f32) as usize;
+        Some((faults[index.min(faults.len() - 1)], Some(random.duration)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SmartDevicePowerState, SmartDeviceStatus, SmartSocket};
+
+    const DISABLED: SmartDeviceStatus =
+        SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled);
+
+    fn malfunction(fault: SmartDeviceErrorCode) -> SmartDeviceStatus {
+        SmartDeviceStatus::Malfunction(fault)
+    }
+
+    #[test]
+    fn test_injected_fault_holds_for_duration() {
+        let mut socket = SmartSocket::new("test", 1);
+        socket.inject_fault(FaultInjection::Inject {
+            fault: SmartDeviceErrorCode::Overheat,
+            duration: Some(Duration::from_secs(10)),
+        });
+        assert_eq!(
+            socket.get_status(),
+            malfunction(SmartDeviceErrorCode::Overheat)
+        );
+
+        // Пока неисправность действует, включение снова отключает розетку
+        socket.reset_fault();
+        assert_eq!(socket.get_status(), DISABLED);
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Err(SmartDeviceErrorCode::Overheat)
+        );
+
+        socket.advance(Duration::from_secs(10));
+        socket.reset_fault();
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_scheduled_fault() {
+        let mut socket = SmartSocket::new("test", 1);
+        socket.inject_fault(FaultInjection::Schedule {
+            delay: Duration::from_secs(5),
+            fault: SmartDeviceErrorCode::Underheat,
+            duration: None,
+        });
+
+        socket.advance(Duration::from_secs(4));
+        assert_eq!(socket.get_status(), DISABLED);
+        socket.advance(Duration::from_secs(1));
+        assert_eq!(
+            socket.get_status(),
+            malfunction(SmartDeviceErrorCode::Underheat)
+        );
+
+        socket.inject_fault(FaultInjection::Clear);
+        socket.reset_fault();
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_random_faults() {
+        let mut injector = FaultInjector::default();
+        injector.apply(FaultInjection::Random(Some(RandomFaults {
+            probability: 0.5,
+            duration: Duration::from_secs(1),
+            faults: vec![SmartDeviceErrorCode::Overvoltage],
+        })));
+
+        let occurred = (0..1000)
+            .filter_map(|_| injector.advance(Duration::from_secs(1)))
+            .collect::<Vec<_>>();
+        assert!((400..600).contains(&occurred.len()), "{}", occurred.len());
+        assert!(occurred
+            .iter()
+            .all(|fault| *fault == SmartDeviceErrorCode::Overvoltage));
+
+        injector.apply(FaultInjection::Random(None));
+        assert!((0..1000).all(|_| injector.advance(Duration::from_secs(1)).is_none()));
+    }
+}
@@ -1,3 +1,24 @@
+pub mod clock;
+pub mod electrical;
+pub mod energy;
+pub mod faults;
+pub mod load;
+pub mod protection;
+pub mod schedule;
+pub mod temperature;
+mod thermometer;
+
+use clock::{Clock, SystemClock};
+use electrical::{ElectricalLimits, ElectricalReadings, LineSupply};
+use energy::{EnergyMeter, EnergyReading};
+use faults::{FaultInjection, FaultInjector};
+use load::{non_negative, ConstantLoad, LoadModel};
+use protection::{Protection, TripEvent};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use thermometer::SmartThermometer;
+
 ///
 /// Тип описывающий характеристики и поведение девайса "Умная розетка"
 ///
@@ -10,16 +31,44 @@ pub struct SmartSocket {
 
     // Cтатус работы (ВКЛ,ВЫКЛ/ОШИБКА)
     status: SmartDeviceStatus,
+
+    /// Модель подключённой нагрузки
+    load: Box<dyn LoadModel>,
+
+    /// Время, прошедшее с момента включения розетки
+    enabled_for: Duration,
+
+    /// Источник текущего времени
+    clock: Arc<dyn Clock>,
+
+    /// Счётчик потреблённой энергии
+    meter: EnergyMeter,
+
+    /// Модель напряжения сети
+    supply: LineSupply,
+
+    /// Последние показания напряжения, тока и коэффициента мощности
+    readings: ElectricalReadings,
+
+    /// Пределы напряжения и тока
+    limits: ElectricalLimits,
+
+    /// Защитное отключение и история отключений
+    protection: Protection,
+
+    /// Внесённые неисправности
+    faults: FaultInjector,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SmartDeviceStatus {
     /// Состояние питания умного устройства
     PowerState(SmartDevicePowerState),
     /// Возможные ошибки в работе умного устройства
     Malfunction(SmartDeviceErrorCode),
 }
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SmartDeviceErrorCode {
     /// Ошибка: перегрузка по току
     Overcurrent,
@@ -35,7 +84,8 @@ pub enum SmartDeviceErrorCode {
 }
 
 /// Перечисление возможных состояний питания умного устройства
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SmartDevicePowerState {
     /// Устройство включено
     Enabled,
@@ -43,10 +93,125 @@ pub enum SmartDevicePowerState {
     Disabled,
 }
 
+/// Вид умного устройства
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SmartDeviceKind {
+    /// Умная розетка
+    Socket,
+    /// Умный термометр
+    Thermometer,
+}
+
+/// Команда умному устройству
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmartDeviceCommand {
+    /// Изменение состояния питания
+    SetPowerState(SmartDevicePowerState),
+    /// Изменение порогов температуры
+    SetTemperatureThresholds(TemperatureThresholds),
+    /// Сброс сбрасываемого счётчика энергии
+    ResetEnergy,
+    /// Изменение пределов напряжения и тока
+    SetElectricalLimits(ElectricalLimits),
+    /// Сброс неисправности после защитного отключения
+    ResetFault,
+    /// Внесение неисправностей для проверки поведения клиентов
+    InjectFault(FaultInjection),
+}
+
+/// Пороги температуры, за пределами которых термометр сообщает о неисправности
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemperatureThresholds {
+    /// Нижний порог, °C: ниже него - `SmartDeviceErrorCode::Underheat`
+    pub low_c: f32,
+    /// Верхний порог, °C: выше него - `SmartDeviceErrorCode::Overheat`
+    pub high_c: f32,
+}
+
+/// Ошибка выполнения команды умным устройством
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmartDeviceCommandError {
+    /// Команда не поддерживается устройством
+    Unsupported,
+    /// Недопустимые параметры команды
+    InvalidArgument,
+    /// Устройство неисправно
+    Malfunction(SmartDeviceErrorCode),
+}
+
+///
+/// Общее поведение умных устройств
+///
+pub trait SmartDevice: Send {
+    /// Вид устройства
+    fn get_kind(&self) -> SmartDeviceKind;
+
+    /// Получение идентификатора устройства
+    fn get_id(&self) -> u8;
+
+    /// Получение имени устройства
+    fn get_name(&self) -> &str;
+
+    /// Изменение имени устройства
+    fn set_name(&mut self, name: &str);
+
+    /// Получение статуса работы устройства
+    fn get_status(&self) -> SmartDeviceStatus;
+
+    /// Получение текущей потребляемой мощности, Вт
+    fn get_power_consumption(&self) -> f32;
+
+    /// Получение текстовой информации о состоянии устройства
+    fn get_text_report(&self) -> String;
+
+    /// Продвижение имитации работы устройства на `elapsed`
+    fn advance(&mut self, _elapsed: Duration) {}
+
+    /// Показания счётчика энергии (`None` - устройство не ведёт учёт энергии)
+    fn get_energy(&self) -> Option<EnergyReading> {
+        None
+    }
+
+    /// Показания напряжения, тока и коэффициента мощности (`None` - устройство их не измеряет)
+    fn get_electrical(&self) -> Option<ElectricalReadings> {
+        None
+    }
+
+    /// Пределы напряжения и тока (`None` - устройство их не контролирует)
+    fn get_electrical_limits(&self) -> Option<ElectricalLimits> {
+        None
+    }
+
+    /// События защитного отключения, от старых к новым (`None` - устройство без защиты)
+    fn get_trip_history(&self) -> Option<Vec<TripEvent>> {
+        None
+    }
+
+    /// Получение текущей температуры, °C (`None` - устройство не измеряет температуру)
+    fn get_temperature(&self) -> Option<f32> {
+        None
+    }
+
+    /// Получение порогов температуры (`None` - устройство не измеряет температуру)
+    fn get_temperature_thresholds(&self) -> Option<TemperatureThresholds> {
+        None
+    }
+
+    /// Выполнение команды
+    fn handle_command(
+        &mut self,
+        command: SmartDeviceCommand,
+    ) -> Result<(), SmartDeviceCommandError>;
+}
+
 impl SmartSocket {
     /// Создание экземпляра умной розетки с псевдонимом `name`
     ///
-    /// По умолчанию розетка выключена, потребление - `0.0 Вт`
+    /// По умолчанию розетка выключена, потребление - `0.0 Вт`,
+    /// нагрузка не подключена (см. `with_load`), сеть - `230 ± 2 В`,
+    /// пределы - `253 В` и `16 А`
     ///
     /// ## Пример
     /// ```ignore
@@ -54,36 +219,263 @@ impl SmartSocket {
     /// ```
     ///
     pub fn new(name: &str, id: u8) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
         Self {
             name: (name.to_string(), id),
             power_consumption: 0.0,
             status: SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled),
+            load: Box::new(ConstantLoad::new(0.0)),
+            enabled_for: Duration::ZERO,
+            meter: EnergyMeter::new(Arc::clone(&clock)),
+            clock,
+            supply: LineSupply::default(),
+            readings: ElectricalReadings::new(electrical::NOMINAL_VOLTAGE_V, 0.0, 1.0),
+            limits: ElectricalLimits::default(),
+            protection: Protection::default(),
+            faults: FaultInjector::default(),
+        }
+    }
+
+    /// Розетка, учитывающая энергию и события по часам `clock` (счётчик энергии создаётся заново)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.meter = EnergyMeter::new(Arc::clone(&clock));
+        self.clock = clock;
+        self.meter.sample(self.power_consumption);
+        self
+    }
+
+    /// Розетка с подключённой нагрузкой `load`
+    pub fn with_load<L: LoadModel + 'static>(mut self, load: L) -> Self {
+        self.set_load(Box::new(load));
+        self
+    }
+
+    /// Подключение к розетке нагрузки `load`, отсчёт времени работы нагрузки начинается заново
+    pub fn set_load(&mut self, load: Box<dyn LoadModel>) {
+        self.load = load;
+        self.enabled_for = Duration::ZERO;
+        self.update_power_consumption();
+    }
+
+    /// Розетка, подключённая к сети `supply`
+    pub fn with_supply(mut self, supply: LineSupply) -> Self {
+        self.set_supply(supply);
+        self
+    }
+
+    /// Подключение розетки к сети `supply`
+    pub fn set_supply(&mut self, supply: LineSupply) {
+        self.supply = supply;
+        self.update_power_consumption();
+    }
+
+    /// Розетка с пределами напряжения и тока `limits`
+    pub fn with_limits(
+        mut self,
+        limits: ElectricalLimits,
+    ) -> Result<Self, SmartDeviceCommandError> {
+        self.set_limits(limits)?;
+        Ok(self)
+    }
+
+    /// Изменение пределов напряжения и тока, пределы должны быть положительны.
+    ///
+    /// Если текущие показания превышают новые пределы, розетка отключается с неисправностью.
+    pub fn set_limits(&mut self, limits: ElectricalLimits) -> Result<(), SmartDeviceCommandError> {
+        if !limits.is_valid() {
+            return Err(SmartDeviceCommandError::InvalidArgument);
+        }
+        self.limits = limits;
+        self.check_limits();
+        Ok(())
+    }
+
+    /// Получение пределов напряжения и тока
+    pub fn get_limits(&self) -> ElectricalLimits {
+        self.limits
+    }
+
+    /// Получение последних показаний напряжения, тока и коэффициента мощности
+    pub fn get_readings(&self) -> ElectricalReadings {
+        self.readings
+    }
+
+    /// Розетка, которая после защитного отключения включается повторно через `cooldown`
+    pub fn with_auto_reclose(mut self, cooldown: Duration) -> Self {
+        self.set_auto_reclose(Some(cooldown));
+        self
+    }
+
+    /// Изменение выдержки перед автоматическим повторным включением
+    /// (`None` - неисправность сбрасывается только командой)
+    pub fn set_auto_reclose(&mut self, cooldown: Option<Duration>) {
+        self.protection.set_auto_reclose(cooldown);
+    }
+
+    /// Выдержка перед автоматическим повторным включением
+    pub fn get_auto_reclose(&self) -> Option<Duration> {
+        self.protection.get_auto_reclose()
+    }
+
+    /// Защитное отключение розетки с неисправностью `fault`.
+    ///
+    /// Розетка остаётся отключённой до сброса неисправности (`reset_fault`)
+    /// или автоматического повторного включения.
+    pub fn trip(&mut self, fault: SmartDeviceErrorCode) {
+        self.protection.record_trip(
+            TripEvent {
+                at: self.clock.now(),
+                fault,
+                voltage_v: self.readings.voltage_v,
+                current_a: self.readings.current_a,
+            },
+            self.status,
+        );
+        self.status = SmartDeviceStatus::Malfunction(fault);
+        self.power_consumption = 0.0;
+        self.readings.current_a = 0.0;
+        self.meter.sample(self.power_consumption);
+    }
+
+    /// Внесение неисправностей, см. модуль `faults`
+    pub fn inject_fault(&mut self, injection: FaultInjection) {
+        if let Some(fault) = self.faults.apply(injection) {
+            self.trip(fault);
         }
     }
 
+    /// Сброс неисправности: розетка остаётся выключенной до команды включения
+    pub fn reset_fault(&mut self) {
+        if let SmartDeviceStatus::Malfunction(_) = self.status {
+            self.status = SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled);
+            self.update_power_consumption();
+        }
+    }
+
+    /// События защитного отключения, от старых к новым
+    pub fn get_trip_history(&self) -> Vec<TripEvent> {
+        self.protection.history()
+    }
+
+    /// Включение/выключение розетки.
+    ///
+    /// При включении отсчёт времени работы нагрузки начинается заново,
+    /// при выключении потребление падает до нуля. Если после включения
+    /// напряжение или ток превышают пределы, розетка сразу отключается
+    /// и возвращается код неисправности.
     pub fn set_power_state(
         &mut self,
         state: SmartDevicePowerState,
     ) -> Result<(), SmartDeviceErrorCode> {
         match &self.status {
-            SmartDeviceStatus::PowerState(_) => {
+            SmartDeviceStatus::PowerState(current) => {
+                if *current != state {
+                    self.enabled_for = Duration::ZERO;
+                }
                 self.status = SmartDeviceStatus::PowerState(state);
-                Ok(())
+                self.update_power_consumption();
+                match self.status {
+                    SmartDeviceStatus::Malfunction(code) => Err(code),
+                    SmartDeviceStatus::PowerState(_) => Ok(()),
+                }
             }
-            SmartDeviceStatus::Malfunction(y) => Err((*y).clone()),
+            SmartDeviceStatus::Malfunction(y) => Err(*y),
         }
     }
 
+    /// Продвижение имитации нагрузки на `elapsed`
+    ///
+    /// Отключённая защитой розетка по истечении выдержки автоматического повторного
+    /// включения возвращается в состояние питания, в котором была перед отключением.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let injected = self.faults.advance(elapsed);
+        match self.status {
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled) => {
+                self.enabled_for += elapsed;
+            }
+            SmartDeviceStatus::Malfunction(_) => {
+                if let Some(state) = self.protection.advance_tripped(elapsed) {
+                    self.status = SmartDeviceStatus::PowerState(state);
+                    self.enabled_for = Duration::ZERO;
+                }
+            }
+            _ => {}
+        }
+        self.update_power_consumption();
+        if let Some(fault) = injected {
+            if self.status != SmartDeviceStatus::Malfunction(fault) {
+                self.trip(fault);
+            }
+        }
+    }
+
+    /// Показания счётчика энергии
+    pub fn get_energy(&self) -> EnergyReading {
+        self.meter.reading()
+    }
+
+    /// Сброс сбрасываемого счётчика энергии
+    pub fn reset_energy(&mut self) {
+        self.meter.reset();
+    }
+
+    /// Пересчёт потребления и показаний: нагрузка питается только от включённой розетки.
+    /// Отрицательная или нечисловая мощность пользовательской модели нагрузки считается нулевой.
+    fn update_power_consumption(&mut self) {
+        let voltage_v = self.supply.voltage();
+        self.power_consumption = match self.status {
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled) => {
+                non_negative(self.load.power(self.enabled_for))
+            }
+            _ => 0.0,
+        };
+        self.readings =
+            ElectricalReadings::new(voltage_v, self.power_consumption, self.load.power_factor());
+        self.check_limits();
+    }
+
+    /// Защитное отключение включённой розетки при превышении пределов
+    /// или действующей внесённой неисправности и учёт энергии по итоговому потреблению
+    fn check_limits(&mut self) {
+        if self.status == SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled) {
+            if let Some(fault) = self.faults.active() {
+                return self.trip(fault);
+            }
+            if self.readings.voltage_v > self.limits.max_voltage_v {
+                return self.trip(SmartDeviceErrorCode::Overvoltage);
+            }
+            if self.readings.current_a > self.limits.max_current_a {
+                return self.trip(SmartDeviceErrorCode::Overcurrent);
+            }
+        }
+        self.meter.sample(self.power_consumption);
+    }
+
     /// Получение имени устройства
     pub fn get_name(&self) -> &str {
         &self.name.0
     }
 
+    /// Изменение имени устройства
+    pub fn set_name(&mut self, name: &str) {
+        self.name.0 = name.to_string();
+    }
+
     /// Получение идентификатора устройства
     pub fn get_id(&self) -> u8 {
         self.name.1
     }
 
+    /// Получение статуса работы устройства
+    pub fn get_status(&self) -> SmartDeviceStatus {
+        self.status
+    }
+
+    /// Получение текущей потребляемой мощности, Вт
+    pub fn get_power_consumption(&self) -> f32 {
+        self.power_consumption
+    }
+
     /// Получение текстовой информации о состоянии устройства
     pub fn get_text_report(&self) -> String {
         format!(
@@ -93,6 +485,83 @@ impl SmartSocket {
     }
 }
 
+impl SmartDevice for SmartSocket {
+    fn get_kind(&self) -> SmartDeviceKind {
+        SmartDeviceKind::Socket
+    }
+
+    fn get_id(&self) -> u8 {
+        SmartSocket::get_id(self)
+    }
+
+    fn get_name(&self) -> &str {
+        SmartSocket::get_name(self)
+    }
+
+    fn set_name(&mut self, name: &str) {
+        SmartSocket::set_name(self, name)
+    }
+
+    fn get_status(&self) -> SmartDeviceStatus {
+        SmartSocket::get_status(self)
+    }
+
+    fn get_power_consumption(&self) -> f32 {
+        SmartSocket::get_power_consumption(self)
+    }
+
+    fn get_text_report(&self) -> String {
+        SmartSocket::get_text_report(self)
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        SmartSocket::advance(self, elapsed)
+    }
+
+    fn get_energy(&self) -> Option<EnergyReading> {
+        Some(SmartSocket::get_energy(self))
+    }
+
+    fn get_electrical(&self) -> Option<ElectricalReadings> {
+        Some(self.readings)
+    }
+
+    fn get_electrical_limits(&self) -> Option<ElectricalLimits> {
+        Some(self.limits)
+    }
+
+    fn get_trip_history(&self) -> Option<Vec<TripEvent>> {
+        Some(SmartSocket::get_trip_history(self))
+    }
+
+    fn handle_command(
+        &mut self,
+        command: SmartDeviceCommand,
+    ) -> Result<(), SmartDeviceCommandError> {
+        match command {
+            SmartDeviceCommand::SetPowerState(state) => self
+                .set_power_state(state)
+                .map_err(SmartDeviceCommandError::Malfunction),
+            SmartDeviceCommand::ResetEnergy => {
+                self.reset_energy();
+                Ok(())
+            }
+            SmartDeviceCommand::SetElectricalLimits(limits) => self.set_limits(limits),
+            SmartDeviceCommand::ResetFault => {
+                self.reset_fault();
+                Ok(())
+            }
+            SmartDeviceCommand::InjectFault(injection) => {
+                self.inject_fault(injection);
+                Ok(())
+            }
+            SmartDeviceCommand::SetTemperatureThresholds(_) => {
+                Err(SmartDeviceCommandError::Unsupported)
+            }
+        }
+    }
+}
+
 use std::fmt::{self, Display};
 
 impl Display for SmartDevicePowerState {
@@ -121,3 +590,103 @@ impl Display for SmartDeviceStatus {
         }
     }
 }
+impl Display for SmartDeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Socket => write!(f, "Smart socket"),
+            Self::Thermometer => write!(f, "Smart thermometer"),
+        }
+    }
+}
+impl Display for SmartDeviceCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unsupported => write!(f, "Unsupported command."),
+            Self::InvalidArgument => write!(f, "Invalid command argument."),
+            Self::Malfunction(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_commands() {
+        let mut socket = SmartSocket::new("lamp", 47);
+        let device: &mut dyn SmartDevice = &mut socket;
+        assert_eq!(device.get_kind(), SmartDeviceKind::Socket);
+
+        device
+            .handle_command(SmartDeviceCommand::SetPowerState(
+                SmartDevicePowerState::Enabled,
+            ))
+            .unwrap();
+        assert_eq!(
+            device.get_status(),
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled)
+        );
+        assert_eq!(
+            device.handle_command(SmartDeviceCommand::SetElectricalLimits(ElectricalLimits {
+                max_voltage_v: f32::NAN,
+                max_current_a: 16.0,
+            })),
+            Err(SmartDeviceCommandError::InvalidArgument)
+        );
+        assert_eq!(
+            device.handle_command(SmartDeviceCommand::SetTemperatureThresholds(
+                TemperatureThresholds {
+                    low_c: 0.0,
+                    high_c: 30.0,
+                }
+            )),
+            Err(SmartDeviceCommandError::Unsupported)
+        );
+        assert!(device.get_energy().is_some());
+        assert!(device.get_temperature().is_none());
+    }
+
+    /// Модель нагрузки с ошибочной отрицательной мощностью
+    struct NegativeLoad;
+
+    impl LoadModel for NegativeLoad {
+        fn power(&mut self, _elapsed: Duration) -> f32 {
+            -100.0
+        }
+    }
+
+    #[test]
+    fn test_invalid_load_power_is_ignored() {
+        let mut socket = SmartSocket::new("lamp", 47).with_load(NegativeLoad);
+        socket
+            .set_power_state(SmartDevicePowerState::Enabled)
+            .unwrap();
+        socket.advance(Duration::from_secs(3600));
+        assert_eq!(socket.get_power_consumption(), 0.0);
+        assert_eq!(socket.get_readings().current_a, 0.0);
+    }
+
+    #[test]
+    fn test_faulty_socket_rejects_power_commands() {
+        let mut socket = SmartSocket::new("kettle", 48);
+        socket.trip(SmartDeviceErrorCode::Overcurrent);
+        let device: &mut dyn SmartDevice = &mut socket;
+
+        assert_eq!(
+            device.handle_command(SmartDeviceCommand::SetPowerState(
+                SmartDevicePowerState::Enabled,
+            )),
+            Err(SmartDeviceCommandError::Malfunction(
+                SmartDeviceErrorCode::Overcurrent
+            ))
+        );
+        device
+            .handle_command(SmartDeviceCommand::ResetFault)
+            .unwrap();
+        assert_eq!(
+            device.get_status(),
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled)
+        );
+    }
+}
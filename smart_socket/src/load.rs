@@ -0,0 +1,418 @@
+//! Модели нагрузки, подключённой к умной розетке.
+//!
+//! Модель задаёт потребляемую мощность в зависимости от времени, прошедшего
+//! с момента включения розетки. Мощность не бывает отрицательной или нечисловой:
+//! такие значения параметров моделей заменяются нулём.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use std::{fmt, fs, io};
+
+/// Модель нагрузки розетки
+pub trait LoadModel: Send {
+    /// Потребляемая мощность, Вт, через `elapsed` после включения розетки
+    fn power(&mut self, elapsed: Duration) -> f32;
+
+    /// Коэффициент мощности нагрузки, `(0.0, 1.0]`
+    fn power_factor(&self) -> f32 {
+        1.0
+    }
+}
+
+/// Нагрузка с постоянной мощностью
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConstantLoad {
+    /// Мощность, Вт
+    pub power_w: f32,
+}
+
+impl ConstantLoad {
+    /// Нагрузка мощностью `power_w`, Вт
+    pub fn new(power_w: f32) -> Self {
+        Self {
+            power_w: non_negative(power_w),
+        }
+    }
+}
+
+impl LoadModel for ConstantLoad {
+    fn power(&mut self, _elapsed: Duration) -> f32 {
+        non_negative(self.power_w)
+    }
+}
+
+/// Нагрузка, мощность которой случайно колеблется около среднего значения
+#[derive(Debug, Clone)]
+pub struct NoisyLoad {
+    /// Средняя мощность, Вт
+    base_w: f32,
+    /// Наибольшее отклонение от средней мощности, Вт
+    amplitude_w: f32,
+    noise: Noise,
+}
+
+impl NoisyLoad {
+    /// Нагрузка мощностью `base_w ± amplitude_w`, Вт
+    pub fn new(base_w: f32, amplitude_w: f32) -> Self {
+        Self {
+            base_w: non_negative(base_w),
+            amplitude_w: non_negative(amplitude_w),
+            noise: Noise::default(),
+        }
+    }
+
+    /// Задание начального значения генератора для воспроизводимых последовательностей
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Noise::with_seed(seed);
+        self
+    }
+}
+
+impl LoadModel for NoisyLoad {
+    fn power(&mut self, _elapsed: Duration) -> f32 {
+        let noise = self.noise.next();
+        (self.base_w + self.amplitude_w * noise).max(0.0)
+    }
+}
+
+/// Значение физической величины (мощности, напряжения): отрицательные
+/// и нечисловые значения заменяются нулём
+pub(crate) fn non_negative(value: f32) -> f32 {
+    if value.is_finite() {
+        value.max(0.0)
+    } else {
+        0.0
+    }
+}
+
+/// Генератор псевдослучайных чисел (xorshift) для имитации помех
+#[derive(Debug, Clone)]
+pub(crate) struct Noise {
+    state: u64,
+}
+
+impl Noise {
+    /// Генератор с начальным значением `seed`
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        // Нулевое состояние xorshift не меняется
+        Self { state: seed.max(1) }
+    }
+
+    /// Псевдослучайное число в диапазоне `[-1.0, 1.0]`
+    pub(crate) fn next(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+/// Генератор со случайным начальным значением: у каждого экземпляра (нагрузки,
+/// сети, внесённых неисправностей) своя последовательность
+impl Default for Noise {
+    fn default() -> Self {
+        // Ключи каждого `RandomState` выбираются случайно
+        Self::with_seed(RandomState::new().build_hasher().finish())
+    }
+}
+
+/// Нагрузка, заданная профилем: мощность меняется ступенчато в заданные моменты времени
+///
+/// До первой точки профиля мощность равна нулю, после последней - сохраняется,
+/// если профиль не зациклен.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileLoad {
+    /// Точки профиля (момент от включения, мощность в Вт) в порядке возрастания времени
+    points: Vec<(Duration, f32)>,
+    /// Период повторения профиля
+    period: Option<Duration>,
+    /// Коэффициент мощности
+    power_factor: f32,
+}
+
+impl ProfileLoad {
+    /// Профиль из точек (момент от включения, мощность в Вт);
+    /// мощность каждой точки должна быть конечной и неотрицательной
+    pub fn new(points: Vec<(Duration, f32)>) -> Result<Self, ProfileError> {
+        if points.is_empty() {
+            return Err(ProfileError::Empty);
+        }
+        if let Some(index) = points
+            .iter()
+            .position(|(_, power)| !power.is_finite() || *power < 0.0)
+        {
+            return Err(ProfileError::InvalidPower { point: index + 1 });
+        }
+        if let Some(index) = points.windows(2).position(|pair| pair[0].0 > pair[1].0) {
+            return Err(ProfileError::UnsortedPoint { point: index + 2 });
+        }
+        Ok(Self {
+            points,
+            period: None,
+            power_factor: 1.0,
+        })
+    }
+
+    /// Разбор профиля в формате CSV: строки `секунды,ватты`.
+    ///
+    /// Пустые строки и строки, начинающиеся с `#`, пропускаются; первая строка
+    /// может быть заголовком, если это не пара чисел. Номер строки в ошибке считается с единицы.
+    pub fn from_csv(text: &str) -> Result<Self, ProfileError> {
+        let mut points = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let point = match parse_csv_point(line) {
+                Some(point) => point,
+                None if index == 0 && !is_numeric_row(line) => continue,
+                None => return Err(ProfileError::InvalidLine { line: index + 1 }),
+            };
+            if points.last().is_some_and(|(at, _)| *at > point.0) {
+                return Err(ProfileError::UnsortedLine { line: index + 1 });
+            }
+            points.push(point);
+        }
+        Self::new(points)
+    }
+
+    /// Чтение профиля из CSV-файла, см. `from_csv`
+    pub fn from_csv_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, ProfileError> {
+        Self::from_csv(&fs::read_to_string(path)?)
+    }
+
+    /// Профиль, повторяющийся с периодом `period`
+    pub fn looped(mut self, period: Duration) -> Self {
+        self.period = (!period.is_zero()).then_some(period);
+        self
+    }
+
+    /// Профиль с коэффициентом мощности `power_factor` (ограничивается диапазоном `[0.01, 1.0]`)
+    pub fn with_power_factor(mut self, power_factor: f32) -> Self {
+        if power_factor.is_finite() {
+            self.power_factor = power_factor.clamp(0.01, 1.0);
+        }
+        self
+    }
+
+    /// Электрочайник: 2 кВт в течение трёх минут, затем отключается
+    pub fn kettle() -> Self {
+        Self {
+            points: vec![(Duration::ZERO, 2000.0), (Duration::from_secs(180), 0.0)],
+            period: None,
+            power_factor: 1.0,
+        }
+    }
+
+    /// Холодильник: пусковой ток компрессора, 15 минут работы и 30 минут простоя;
+    /// двигатель компрессора - индуктивная нагрузка
+    pub fn fridge() -> Self {
+        Self {
+            points: vec![
+                (Duration::ZERO, 600.0),
+                (Duration::from_secs(2), 120.0),
+                (Duration::from_secs(15 * 60), 5.0),
+            ],
+            period: Some(Duration::from_secs(45 * 60)),
+            power_factor: 0.8,
+        }
+    }
+}
+
+impl LoadModel for ProfileLoad {
+    fn power(&mut self, elapsed: Duration) -> f32 {
+        let elapsed = match self.period {
+            Some(period) => Duration::from_nanos((elapsed.as_nanos() % period.as_nanos()) as u64),
+            None => elapsed,
+        };
+        self.points
+            .iter()
+            .take_while(|(at, _)| *at <= elapsed)
+            .last()
+            .map_or(0.0, |(_, power)| *power)
+    }
+
+    fn power_factor(&self) -> f32 {
+        self.power_factor
+    }
+}
+
+/// Точка профиля из строки CSV `секунды,ватты`
+fn parse_csv_point(line: &str) -> Option<(Duration, f32)> {
+    let (seconds, power) = line.split_once(',')?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    let power: f32 = power.trim().parse().ok()?;
+    if !power.is_finite() || power < 0.0 {
+        return None;
+    }
+    Some((Duration::try_from_secs_f64(seconds).ok()?, power))
+}
+
+/// Строка CSV из двух чисел (возможно, недопустимых), а не заголовок
+fn is_numeric_row(line: &str) -> bool {
+    line.split_once(',').is_some_and(|(seconds, power)| {
+        seconds.trim().parse::<f64>().is_ok() && power.trim().parse::<f32>().is_ok()
+    })
+}
+
+/// Ошибка загрузки профиля нагрузки
+#[derive(Debug)]
+pub enum ProfileError {
+    /// Профиль не содержит ни одной точки
+    Empty,
+
+    /// Строка не является парой `секунды,ватты`
+    InvalidLine { line: usize },
+
+    /// Мощность точки профиля отрицательна или не является числом (номер точки, считая с единицы)
+    InvalidPower { point: usize },
+
+    /// Строка CSV с моментом раньше предыдущей строки (номер строки, считая с единицы)
+    UnsortedLine { line: usize },
+
+    /// Точка профиля с моментом раньше предыдущей точки (номер точки, считая с единицы)
+    UnsortedPoint { point: usize },
+
+    /// Ошибка чтения файла
+    Io(io::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Empty => write!(f, "load profile is empty"),
+            ProfileError::InvalidLine { line } => {
+                write!(f, "line {line}: expected 'seconds,watts'")
+            }
+            ProfileError::InvalidPower { point } => {
+                write!(
+                    f,
+                    "point {point}: power must be a finite non-negative number"
+                )
+            }
+            ProfileError::UnsortedLine { line } => {
+                write!(f, "line {line}: profile points must be sorted by time")
+            }
+            ProfileError::UnsortedPoint { point } => {
+                write!(f, "point {point}: profile points must be sorted by time")
+            }
+            ProfileError::Io(e) => write!(f, "Internal IO error occured: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ProfileError {
+    fn from(e: io::Error) -> Self {
+        ProfileError::Io(e)
+    }
+}
+
+impl Error for ProfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProfileError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_from_csv() {
+        let csv = "seconds,watts\n# разогрев\n0,1500\n\n60.5, 200\n120,0\n";
+        let mut profile = ProfileLoad::from_csv(csv).unwrap();
+
+        assert_eq!(profile.power(Duration::ZERO), 1500.0);
+        assert_eq!(profile.power(Duration::from_secs(60)), 1500.0);
+        assert_eq!(profile.power(Duration::from_millis(60_500)), 200.0);
+        assert_eq!(profile.power(Duration::from_secs(3600)), 0.0);
+    }
+
+    #[test]
+    fn test_profile_csv_errors() {
+        assert!(matches!(
+            ProfileLoad::from_csv("seconds,watts\n"),
+            Err(ProfileError::Empty)
+        ));
+        assert!(matches!(
+            ProfileLoad::from_csv("0,10\nten,20\n"),
+            Err(ProfileError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            ProfileLoad::from_csv("0,10\n5,-1\n"),
+            Err(ProfileError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            ProfileLoad::from_csv("10,10\n# comment\n5,20\n"),
+            Err(ProfileError::UnsortedLine { line: 3 })
+        ));
+        for csv in ["0,-100\n60,200\n", "0,NaN\n60,200\n", "0,inf\n"] {
+            assert!(
+                matches!(
+                    ProfileLoad::from_csv(csv),
+                    Err(ProfileError::InvalidLine { line: 1 })
+                ),
+                "{csv}"
+            );
+        }
+        let points = vec![(Duration::from_secs(10), 1.0), (Duration::ZERO, 2.0)];
+        assert!(matches!(
+            ProfileLoad::new(points),
+            Err(ProfileError::UnsortedPoint { point: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_power_is_rejected() {
+        for power in [-100.0, f32::NAN, f32::INFINITY] {
+            let points = vec![(Duration::ZERO, 100.0), (Duration::from_secs(60), power)];
+            assert!(matches!(
+                ProfileLoad::new(points),
+                Err(ProfileError::InvalidPower { point: 2 })
+            ));
+        }
+
+        let mut negative = ConstantLoad::new(-5.0);
+        assert_eq!(negative.power(Duration::ZERO), 0.0);
+        let mut nan = ConstantLoad { power_w: f32::NAN };
+        assert_eq!(nan.power(Duration::ZERO), 0.0);
+        let mut noisy = NoisyLoad::new(f32::NAN, -10.0);
+        assert_eq!(noisy.power(Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut kettle = ProfileLoad::kettle();
+        assert_eq!(kettle.power(Duration::from_secs(60)), 2000.0);
+        assert_eq!(kettle.power(Duration::from_secs(600)), 0.0);
+
+        let mut fridge = ProfileLoad::fridge();
+        assert_eq!(fridge.power(Duration::from_secs(60)), 120.0);
+        assert_eq!(fridge.power(Duration::from_secs(20 * 60)), 5.0);
+        assert_eq!(fridge.power(Duration::from_secs(46 * 60)), 120.0);
+    }
+
+    #[test]
+    fn test_noisy_load_stays_in_range() {
+        let mut load = NoisyLoad::new(100.0, 10.0).with_seed(7);
+        for _ in 0..10_000 {
+            let power = load.power(Duration::ZERO);
+            assert!((90.0..=110.0).contains(&power), "{power}");
+        }
+        let mut load = NoisyLoad::new(1.0, 10.0);
+        assert!((0..1000).all(|_| load.power(Duration::ZERO) >= 0.0));
+    }
+
+    #[test]
+    fn test_noise_sequences_differ() {
+        let sequence = |mut noise: Noise| (0..8).map(|_| noise.next()).collect::<Vec<_>>();
+        assert_ne!(sequence(Noise::default()), sequence(Noise::default()));
+        assert_eq!(sequence(Noise::with_seed(7)), sequence(Noise::with_seed(7)));
+    }
+}
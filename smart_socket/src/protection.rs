@@ -0,0 +1,177 @@
+//! Защитное отключение розетки и восстановление после неисправности.
+//!
+//! Розетка, у которой напряжение или ток превысили пределы (или которая переведена
+//! в неисправность вручную), отключается и остаётся в состоянии неисправности,
+//! пока неисправность не будет сброшена командой либо, если включено
+//! автоматическое повторное включение, пока не истечёт выдержка времени.
+//! После выдержки розетка возвращается в состояние, в котором была перед отключением:
+//! включённая - включается повторно, выключенная - остаётся выключенной.
+
+use crate::{SmartDeviceErrorCode, SmartDevicePowerState, SmartDeviceStatus};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// Число хранимых событий отключения
+const TRIP_HISTORY_LENGTH: usize = 32;
+
+/// Событие защитного отключения
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TripEvent {
+    /// Момент отключения
+    pub at: SystemTime,
+    /// Причина отключения
+    pub fault: SmartDeviceErrorCode,
+    /// Напряжение в момент отключения, В
+    pub voltage_v: f32,
+    /// Ток в момент отключения, А
+    pub current_a: f32,
+}
+
+/// Состояние защиты розетки
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Protection {
+    /// Выдержка перед автоматическим повторным включением (`None` - только ручной сброс)
+    auto_reclose: Option<Duration>,
+    /// Время, прошедшее с последнего отключения
+    tripped_for: Duration,
+    /// Состояние питания перед отключением (`None` - розетка не отключалась)
+    power_state: Option<SmartDevicePowerState>,
+    /// Последние события отключения, от старых к новым
+    history: VecDeque<TripEvent>,
+}
+
+impl Protection {
+    /// Выдержка перед автоматическим повторным включением
+    pub(crate) fn get_auto_reclose(&self) -> Option<Duration> {
+        self.auto_reclose
+    }
+
+    /// Изменение выдержки перед автоматическим повторным включением
+    pub(crate) fn set_auto_reclose(&mut self, cooldown: Option<Duration>) {
+        self.auto_reclose = cooldown;
+    }
+
+    /// Учёт отключения розетки, находившейся в состоянии `status`;
+    /// при повторном отключении неисправной розетки сохраняется прежнее состояние питания
+    pub(crate) fn record_trip(&mut self, event: TripEvent, status: SmartDeviceStatus) {
+        self.tripped_for = Duration::ZERO;
+        if let SmartDeviceStatus::PowerState(state) = status {
+            self.power_state = Some(state);
+        }
+        if self.history.len() == TRIP_HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(event);
+    }
+
+    /// Продвижение времени в отключённом состоянии на `elapsed`;
+    /// возвращает состояние питания, в которое пора вернуть розетку
+    pub(crate) fn advance_tripped(&mut self, elapsed: Duration) -> Option<SmartDevicePowerState> {
+        self.tripped_for += elapsed;
+        self.auto_reclose
+            .filter(|cooldown| self.tripped_for >= *cooldown)
+            .map(|_| self.power_state.unwrap_or(SmartDevicePowerState::Disabled))
+    }
+
+    /// События отключения, от старых к новым
+    pub(crate) fn history(&self) -> Vec<TripEvent> {
+        self.history.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::electrical::{ElectricalLimits, LineSupply};
+    use crate::load::ConstantLoad;
+    use crate::SmartSocket;
+    use std::sync::Arc;
+
+    const ENABLED: SmartDeviceStatus =
+        SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled);
+    const DISABLED: SmartDeviceStatus =
+        SmartDeviceStatus::PowerState(SmartDevicePowerState::Disabled);
+    const OVERCURRENT: SmartDeviceStatus =
+        SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overcurrent);
+
+    fn overloaded_socket() -> SmartSocket {
+        let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+        SmartSocket::new("test", 1)
+            .with_clock(clock)
+            .with_supply(LineSupply::new(230.0, 0.0))
+            .with_load(ConstantLoad::new(2300.0))
+            .with_limits(ElectricalLimits {
+                max_voltage_v: 253.0,
+                max_current_a: 5.0,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_trip_and_manual_reset() {
+        let mut socket = overloaded_socket();
+
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Err(SmartDeviceErrorCode::Overcurrent)
+        );
+        socket.advance(Duration::from_secs(3600));
+        assert_eq!(socket.get_status(), OVERCURRENT);
+
+        socket.reset_fault();
+        assert_eq!(socket.get_status(), DISABLED);
+
+        let history = socket.get_trip_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].fault, SmartDeviceErrorCode::Overcurrent);
+        assert_eq!(history[0].at, SystemTime::UNIX_EPOCH);
+        assert!((history[0].current_a - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_auto_reclose_after_cooldown() {
+        let mut socket = overloaded_socket().with_auto_reclose(Duration::from_secs(10));
+        assert_eq!(
+            socket.set_power_state(SmartDevicePowerState::Enabled),
+            Err(SmartDeviceErrorCode::Overcurrent)
+        );
+        socket.trip(SmartDeviceErrorCode::Overvoltage);
+
+        socket.advance(Duration::from_secs(5));
+        assert_eq!(
+            socket.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overvoltage)
+        );
+
+        // Перегрузка не устранена: после повторного включения розетка снова отключается
+        socket.advance(Duration::from_secs(5));
+        assert_eq!(socket.get_status(), OVERCURRENT);
+        assert_eq!(socket.get_trip_history().len(), 3);
+
+        socket.set_load(Box::new(ConstantLoad::new(100.0)));
+        socket.advance(Duration::from_secs(10));
+        assert_eq!(socket.get_status(), ENABLED);
+        assert_eq!(socket.get_power_consumption(), 100.0);
+    }
+
+    #[test]
+    fn test_auto_reclose_keeps_disabled_socket_off() {
+        let mut socket = overloaded_socket().with_auto_reclose(Duration::from_secs(10));
+        socket.trip(SmartDeviceErrorCode::Overheat);
+
+        socket.advance(Duration::from_secs(10));
+        assert_eq!(socket.get_status(), DISABLED);
+        assert_eq!(socket.get_power_consumption(), 0.0);
+        assert_eq!(socket.get_trip_history().len(), 1);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut socket = overloaded_socket();
+        for _ in 0..TRIP_HISTORY_LENGTH + 5 {
+            socket.trip(SmartDeviceErrorCode::Overheat);
+        }
+        assert_eq!(socket.get_trip_history().len(), TRIP_HISTORY_LENGTH);
+    }
+}
@@ -0,0 +1,162 @@
+//! Недельные расписания включения и выключения устройств.
+//!
+//! Время расписаний задаётся по UTC, как и суточные счётчики энергии.
+
+use crate::clock::{days_since_epoch, DAY};
+use crate::SmartDevicePowerState;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// День недели
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Дни недели, начиная с понедельника
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Рабочие дни
+    pub const WEEKDAYS: [Weekday; 5] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+    ];
+
+    /// День недели для суток с номером `days` от 1970-01-01 (четверг)
+    pub fn from_days(days: u64) -> Self {
+        Self::ALL[((days + 3) % 7) as usize]
+    }
+
+    /// День недели момента `time` по UTC
+    pub fn of(time: SystemTime) -> Self {
+        Self::from_days(days_since_epoch(time))
+    }
+}
+
+/// Время суток по UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeOfDay {
+    /// Часы, `0..=23`
+    pub hour: u8,
+    /// Минуты, `0..=59`
+    pub minute: u8,
+}
+
+impl TimeOfDay {
+    /// Время `hour:minute`, `None` - время вне суток
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(Self { hour, minute })
+    }
+
+    /// Время от начала суток
+    pub fn since_midnight(&self) -> Duration {
+        Duration::from_secs(u64::from(self.hour) * 3600 + u64::from(self.minute) * 60)
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// Недельное расписание: установка состояния питания в заданное время в заданные дни
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WeeklySchedule {
+    /// Дни недели
+    pub days: Vec<Weekday>,
+    /// Время срабатывания
+    pub at: TimeOfDay,
+    /// Устанавливаемое состояние питания
+    pub action: SmartDevicePowerState,
+}
+
+impl WeeklySchedule {
+    /// Расписание задаёт хотя бы один день и допустимое время
+    pub fn is_valid(&self) -> bool {
+        !self.days.is_empty() && TimeOfDay::new(self.at.hour, self.at.minute).is_some()
+    }
+
+    /// Ближайший после `time` момент срабатывания, `None` - расписание не срабатывает
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        if !self.is_valid() {
+            return None;
+        }
+        let today = days_since_epoch(time);
+        (today..=today + 7)
+            .filter(|day| self.days.contains(&Weekday::from_days(*day)))
+            .map(|day| SystemTime::UNIX_EPOCH + DAY * day as u32 + self.at.since_midnight())
+            .find(|at| *at > time)
+    }
+}
+
+impl fmt::Display for WeeklySchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} at {} UTC on {:?}", self.action, self.at, self.days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Date;
+
+    /// 2026-10-16 06:30:00 UTC, пятница
+    const FRIDAY_MORNING: u64 = 1_792_132_200;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_weekday() {
+        assert_eq!(Weekday::from_days(0), Weekday::Thursday);
+        assert_eq!(Weekday::of(at(FRIDAY_MORNING)), Weekday::Friday);
+        assert_eq!(Date::of(at(FRIDAY_MORNING)).to_string(), "2026-10-16");
+    }
+
+    #[test]
+    fn test_next_after() {
+        let schedule = WeeklySchedule {
+            days: Weekday::WEEKDAYS.to_vec(),
+            at: TimeOfDay::new(7, 0).unwrap(),
+            action: SmartDevicePowerState::Enabled,
+        };
+
+        // В пятницу в 06:30 - через полчаса
+        let friday = at(FRIDAY_MORNING + 30 * 60);
+        assert_eq!(schedule.next_after(at(FRIDAY_MORNING)), Some(friday));
+        // Момент срабатывания не повторяется, после пятницы - понедельник
+        let monday = friday + DAY * 3;
+        assert_eq!(schedule.next_after(friday), Some(monday));
+        assert_eq!(Weekday::of(monday), Weekday::Monday);
+
+        let never = WeeklySchedule {
+            days: Vec::new(),
+            ..schedule
+        };
+        assert_eq!(never.next_after(friday), None);
+        assert_eq!(TimeOfDay::new(24, 0), None);
+    }
+}
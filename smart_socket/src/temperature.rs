@@ -0,0 +1,93 @@
+//! Модели температуры, измеряемой умным термометром.
+//!
+//! Модель задаёт температуру в зависимости от времени, прошедшего
+//! с начала работы термометра.
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+/// Модель измеряемой температуры
+pub trait TemperatureModel: Send {
+    /// Температура, °C, через `elapsed` после начала работы термометра
+    fn temperature(&mut self, elapsed: Duration) -> f32;
+}
+
+/// Постоянная температура
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConstantTemperature {
+    /// Температура, °C
+    pub celsius: f32,
+}
+
+impl ConstantTemperature {
+    /// Температура `celsius`, °C
+    pub fn new(celsius: f32) -> Self {
+        Self { celsius }
+    }
+}
+
+impl TemperatureModel for ConstantTemperature {
+    fn temperature(&mut self, _elapsed: Duration) -> f32 {
+        self.celsius
+    }
+}
+
+/// Температура, плавно колеблющаяся около среднего значения (например, суточный ход)
+///
+/// В начале работы температура равна средней и растёт первую четверть периода.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CyclicTemperature {
+    /// Средняя температура, °C
+    mean_c: f32,
+    /// Наибольшее отклонение от средней температуры, °C
+    amplitude_c: f32,
+    /// Период колебаний
+    period: Duration,
+}
+
+impl CyclicTemperature {
+    /// Температура `mean_c ± amplitude_c`, °C, с периодом `period`;
+    /// при нулевом периоде температура не меняется
+    pub fn new(mean_c: f32, amplitude_c: f32, period: Duration) -> Self {
+        Self {
+            mean_c,
+            amplitude_c,
+            period,
+        }
+    }
+
+    /// Суточный ход `20 ± 5 °C`
+    pub fn daily() -> Self {
+        Self::new(20.0, 5.0, Duration::from_secs(24 * 3600))
+    }
+}
+
+impl TemperatureModel for CyclicTemperature {
+    fn temperature(&mut self, elapsed: Duration) -> f32 {
+        if self.period.is_zero() {
+            return self.mean_c;
+        }
+        let phase = (elapsed.as_secs_f64() / self.period.as_secs_f64()).fract();
+        self.mean_c + self.amplitude_c * (TAU * phase).sin() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cyclic_temperature() {
+        let mut model = CyclicTemperature::new(20.0, 5.0, Duration::from_secs(400));
+        let at = |model: &mut CyclicTemperature, secs| model.temperature(Duration::from_secs(secs));
+
+        assert_eq!(at(&mut model, 0), 20.0);
+        assert!((at(&mut model, 100) - 25.0).abs() < 1e-4);
+        assert!((at(&mut model, 200) - 20.0).abs() < 1e-4);
+        assert!((at(&mut model, 300) - 15.0).abs() < 1e-4);
+        assert!((at(&mut model, 500) - 25.0).abs() < 1e-4);
+
+        let mut flat = CyclicTemperature::new(20.0, 5.0, Duration::ZERO);
+        assert_eq!(flat.temperature(Duration::from_secs(100)), 20.0);
+    }
+}
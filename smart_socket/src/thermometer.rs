@@ -0,0 +1,292 @@
+use crate::temperature::{CyclicTemperature, TemperatureModel};
+use crate::{
+    SmartDevice, SmartDeviceCommand, SmartDeviceCommandError, SmartDeviceErrorCode,
+    SmartDeviceKind, SmartDevicePowerState, SmartDeviceStatus, TemperatureThresholds,
+};
+use std::time::Duration;
+
+/// Нижний порог температуры по умолчанию, °C
+const DEFAULT_LOW_THRESHOLD_C: f32 = 0.0;
+
+/// Верхний порог температуры по умолчанию, °C
+const DEFAULT_HIGH_THRESHOLD_C: f32 = 50.0;
+
+///
+/// Тип описывающий характеристики и поведение девайса "Умный термометр"
+///
+pub struct SmartThermometer {
+    /// Пользовательский псевдоним для термометра
+    pub name: (String, u8),
+
+    /// Текущая температура, °C
+    temperature: f32,
+
+    /// Пороги температуры
+    thresholds: TemperatureThresholds,
+
+    /// Модель измеряемой температуры
+    model: Box<dyn TemperatureModel>,
+
+    /// Время, прошедшее с начала работы термометра
+    running_for: Duration,
+
+    // Cтатус работы (ВКЛ/ОШИБКА)
+    status: SmartDeviceStatus,
+}
+
+impl SmartThermometer {
+    /// Создание экземпляра умного термометра с псевдонимом `name`
+    ///
+    /// По умолчанию термометр включён, температура - `20.0 °C` с суточным ходом
+    /// `± 5 °C` (см. `with_model`), пороги - от `0.0 °C` до `50.0 °C`
+    pub fn new(name: &str, id: u8) -> Self {
+        Self {
+            name: (name.to_string(), id),
+            temperature: 20.0,
+            thresholds: TemperatureThresholds {
+                low_c: DEFAULT_LOW_THRESHOLD_C,
+                high_c: DEFAULT_HIGH_THRESHOLD_C,
+            },
+            model: Box::new(CyclicTemperature::daily()),
+            running_for: Duration::ZERO,
+            status: SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled),
+        }
+    }
+
+    /// Термометр, температура которого задаётся моделью `model`
+    pub fn with_model<M: TemperatureModel + 'static>(mut self, model: M) -> Self {
+        self.set_model(Box::new(model));
+        self
+    }
+
+    /// Замена модели температуры, отсчёт времени работы модели начинается заново
+    pub fn set_model(&mut self, model: Box<dyn TemperatureModel>) {
+        self.model = model;
+        self.running_for = Duration::ZERO;
+        let temperature = self.model.temperature(self.running_for);
+        self.set_temperature(temperature);
+    }
+
+    /// Продвижение имитации температуры на `elapsed`
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.running_for += elapsed;
+        let temperature = self.model.temperature(self.running_for);
+        self.set_temperature(temperature);
+    }
+
+    /// Получение текущей температуры, °C
+    pub fn get_temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    /// Обновление показаний температуры, °C, до следующего `advance`
+    ///
+    /// Выход за пороги переводит термометр в состояние `Overheat` или `Underheat`,
+    /// возврат в допустимый диапазон снимает ошибку.
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = temperature;
+        self.update_status();
+    }
+
+    /// Получение порогов температуры
+    pub fn get_thresholds(&self) -> TemperatureThresholds {
+        self.thresholds
+    }
+
+    /// Изменение порогов температуры, нижний порог должен быть меньше верхнего
+    pub fn set_thresholds(
+        &mut self,
+        thresholds: TemperatureThresholds,
+    ) -> Result<(), SmartDeviceCommandError> {
+        // Пороги NaN несравнимы и тоже отклоняются
+        if thresholds.low_c.partial_cmp(&thresholds.high_c) != Some(std::cmp::Ordering::Less) {
+            return Err(SmartDeviceCommandError::InvalidArgument);
+        }
+        self.thresholds = thresholds;
+        self.update_status();
+        Ok(())
+    }
+
+    /// Пересчёт статуса по текущей температуре и порогам
+    fn update_status(&mut self) {
+        self.status = if self.temperature > self.thresholds.high_c {
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overheat)
+        } else if self.temperature < self.thresholds.low_c {
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Underheat)
+        } else {
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled)
+        };
+    }
+}
+
+impl SmartDevice for SmartThermometer {
+    fn get_kind(&self) -> SmartDeviceKind {
+        SmartDeviceKind::Thermometer
+    }
+
+    fn get_id(&self) -> u8 {
+        self.name.1
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name.0
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name.0 = name.to_string();
+    }
+
+    fn get_status(&self) -> SmartDeviceStatus {
+        self.status
+    }
+
+    /// Потребление термометра не учитывается
+    fn get_power_consumption(&self) -> f32 {
+        0.0
+    }
+
+    fn get_text_report(&self) -> String {
+        format!(
+            "#{}: current temperature is {}, status: {} \n",
+            self.name.0, self.temperature, self.status
+        )
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        SmartThermometer::advance(self, elapsed)
+    }
+
+    fn get_temperature(&self) -> Option<f32> {
+        Some(self.temperature)
+    }
+
+    fn get_temperature_thresholds(&self) -> Option<TemperatureThresholds> {
+        Some(self.thresholds)
+    }
+
+    /// Термометр всегда включён, команды управления питанием не поддерживаются;
+    /// неисправность снимается сама при возврате температуры в пределы порогов
+    fn handle_command(
+        &mut self,
+        command: SmartDeviceCommand,
+    ) -> Result<(), SmartDeviceCommandError> {
+        match command {
+            SmartDeviceCommand::SetPowerState(_)
+            | SmartDeviceCommand::ResetEnergy
+            | SmartDeviceCommand::SetElectricalLimits(_)
+            | SmartDeviceCommand::ResetFault
+            | SmartDeviceCommand::InjectFault(_) => Err(SmartDeviceCommandError::Unsupported),
+            SmartDeviceCommand::SetTemperatureThresholds(thresholds) => {
+                self.set_thresholds(thresholds)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::electrical::ElectricalLimits;
+    use crate::faults::FaultInjection;
+    use crate::temperature::ConstantTemperature;
+
+    const ENABLED: SmartDeviceStatus =
+        SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled);
+
+    fn thresholds(low_c: f32, high_c: f32) -> TemperatureThresholds {
+        TemperatureThresholds { low_c, high_c }
+    }
+
+    #[test]
+    fn test_threshold_crossing() {
+        let mut thermometer = SmartThermometer::new("hall", 50);
+        thermometer.set_thresholds(thresholds(5.0, 30.0)).unwrap();
+        assert_eq!(thermometer.get_status(), ENABLED);
+
+        thermometer.set_temperature(30.5);
+        assert_eq!(
+            thermometer.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overheat)
+        );
+        thermometer.set_temperature(30.0);
+        assert_eq!(thermometer.get_status(), ENABLED);
+
+        thermometer.set_temperature(4.5);
+        assert_eq!(
+            thermometer.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Underheat)
+        );
+        thermometer.set_thresholds(thresholds(0.0, 30.0)).unwrap();
+        assert_eq!(thermometer.get_status(), ENABLED);
+    }
+
+    #[test]
+    fn test_invalid_thresholds_are_rejected() {
+        let mut thermometer = SmartThermometer::new("hall", 50);
+        for invalid in [
+            thresholds(30.0, 30.0),
+            thresholds(30.0, 5.0),
+            thresholds(f32::NAN, 30.0),
+            thresholds(5.0, f32::NAN),
+        ] {
+            assert_eq!(
+                thermometer.set_thresholds(invalid),
+                Err(SmartDeviceCommandError::InvalidArgument)
+            );
+        }
+        assert_eq!(thermometer.get_thresholds(), thresholds(0.0, 50.0));
+    }
+
+    #[test]
+    fn test_temperature_follows_model() {
+        let mut thermometer = SmartThermometer::new("hall", 50);
+        assert_eq!(thermometer.get_temperature(), 20.0);
+        thermometer.advance(Duration::from_secs(6 * 3600));
+        assert!((thermometer.get_temperature() - 25.0).abs() < 1e-3);
+
+        let mut thermometer = thermometer.with_model(ConstantTemperature::new(60.0));
+        assert_eq!(
+            thermometer.get_status(),
+            SmartDeviceStatus::Malfunction(SmartDeviceErrorCode::Overheat)
+        );
+        thermometer.set_temperature(20.0);
+        assert_eq!(thermometer.get_status(), ENABLED);
+        thermometer.advance(Duration::from_secs(1));
+        assert_eq!(thermometer.get_temperature(), 60.0);
+    }
+
+    #[test]
+    fn test_power_commands_are_unsupported() {
+        let mut thermometer = SmartThermometer::new("hall", 50);
+        let device: &mut dyn SmartDevice = &mut thermometer;
+        assert_eq!(device.get_kind(), SmartDeviceKind::Thermometer);
+
+        for command in [
+            SmartDeviceCommand::SetPowerState(SmartDevicePowerState::Disabled),
+            SmartDeviceCommand::ResetEnergy,
+            SmartDeviceCommand::SetElectricalLimits(ElectricalLimits::default()),
+            SmartDeviceCommand::ResetFault,
+            SmartDeviceCommand::InjectFault(FaultInjection::Clear),
+        ] {
+            assert_eq!(
+                device.handle_command(command),
+                Err(SmartDeviceCommandError::Unsupported)
+            );
+        }
+        assert_eq!(
+            device.get_status(),
+            SmartDeviceStatus::PowerState(SmartDevicePowerState::Enabled)
+        );
+        assert!(device.get_energy().is_none());
+        assert!(device.get_electrical().is_none());
+
+        let thresholds = TemperatureThresholds {
+            low_c: 5.0,
+            high_c: 30.0,
+        };
+        device
+            .handle_command(SmartDeviceCommand::SetTemperatureThresholds(thresholds))
+            .unwrap();
+        assert_eq!(device.get_temperature_thresholds(), Some(thresholds));
+    }
+}